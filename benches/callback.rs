@@ -0,0 +1,95 @@
+//! time the audio callback needs to hand the samples over to the analysis thread,
+//! comparing the lock-free ring buffer with sending a copy through a channel
+//!
+//! run with `cargo bench --features bench --bench callback`
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+use audiovis::ring;
+
+// 512 stereo frames, a common callback size
+const BLOCK: usize = 1024;
+const CALLBACKS: usize = 100_000;
+
+fn main() {
+    let data: Vec<f32> = (0..BLOCK).map(|i| (i as f32 * 0.01).sin()).collect();
+
+    let stats = Arc::new(ring::Stats::default());
+    let (mut producer, mut consumer) = ring::channel(48000 * 2, stats.clone());
+    let times = measure(
+        |data| {
+            producer.push(data);
+        },
+        move |running| {
+            let mut out = Vec::with_capacity(48000 * 2);
+            while running.load(Ordering::Relaxed) {
+                consumer.pop_into(&mut out);
+                out.clear();
+                thread::yield_now();
+            }
+        },
+        &data,
+    );
+    report("ring buffer", times);
+    println!("  overruns: {}, dropped samples: {}", stats.overruns(), stats.dropped());
+
+    let (sender, receiver) = mpsc::channel::<Vec<f32>>();
+    let times = measure(
+        |data| {
+            sender.send(data.to_vec()).ok();
+        },
+        move |running| {
+            while running.load(Ordering::Relaxed) {
+                receiver.try_iter().for_each(drop);
+                thread::yield_now();
+            }
+        },
+        &data,
+    );
+    report("mpsc + to_vec", times);
+}
+
+// calls `callback` like a device would and returns the time of every call,
+// while `drain` reads on another thread until the calls are done
+fn measure<C, D>(mut callback: C, drain: D, data: &[f32]) -> Vec<Duration>
+where
+    C: FnMut(&[f32]),
+    D: FnOnce(Arc<AtomicBool>) + Send + 'static,
+{
+    let running = Arc::new(AtomicBool::new(true));
+    let reader = {
+        let running = running.clone();
+        thread::spawn(move || drain(running))
+    };
+
+    let mut times = Vec::with_capacity(CALLBACKS);
+    for i in 0..CALLBACKS {
+        let start = Instant::now();
+        callback(data);
+        times.push(start.elapsed());
+        // roughly the pace of a device, without waiting for the whole block
+        if i % 64 == 0 {
+            thread::sleep(Duration::from_micros(100));
+        }
+    }
+
+    running.store(false, Ordering::Relaxed);
+    reader.join().unwrap();
+    times
+}
+
+fn report(name: &str, mut times: Vec<Duration>) {
+    times.sort();
+    let mean = times.iter().sum::<Duration>() / times.len() as u32;
+    let percentile = |p: f64| times[((times.len() - 1) as f64 * p) as usize];
+    println!(
+        "{}: mean {:?}, p99 {:?}, p99.9 {:?}, max {:?}",
+        name,
+        mean,
+        percentile(0.99),
+        percentile(0.999),
+        times[times.len() - 1],
+    );
+}
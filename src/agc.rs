@@ -0,0 +1,143 @@
+use crate::config::Agc;
+
+/// automatic gain control that pulls the peak of the spectrum towards a target level
+pub struct AutoGain {
+    config: Agc,
+    gain: f32,
+}
+
+impl AutoGain {
+    pub fn new(config: Agc) -> Self {
+        Self {
+            config,
+            gain: 1.0,
+        }
+    }
+
+    /// turns the gain control on or off and returns the new state
+    pub fn toggle(&mut self) -> bool {
+        self.config.enabled = !self.config.enabled;
+        self.config.enabled
+    }
+
+    /// adapts the gain to the peak of `buffer` and applies it,
+    /// `delta` is the time in seconds since the last call
+    pub fn process(&mut self, buffer: &mut [f32], delta: f32) {
+        if !self.config.enabled {
+            return;
+        }
+
+        let peak = buffer.iter().fold(0.0_f32, |peak, value| peak.max(*value));
+
+        // dont blow up noise when nothing is playing
+        if peak > self.config.silence_threshold {
+            let wanted_gain = (self.config.target / peak)
+                .clamp(self.config.min_gain, self.config.max_gain);
+
+            let time_constant = if wanted_gain < self.gain {
+                self.config.attack
+            } else {
+                self.config.release
+            };
+            let coefficient = if time_constant > 0.0 {
+                1.0 - (-delta / time_constant).exp()
+            } else {
+                1.0
+            };
+
+            self.gain += (wanted_gain - self.gain) * coefficient;
+        }
+
+        for value in buffer.iter_mut() {
+            *value *= self.gain;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn agc() -> AutoGain {
+        AutoGain::new(Agc {
+            enabled: true,
+            target: 1.0,
+            attack: 0.1,
+            release: 1.0,
+            min_gain: 0.25,
+            max_gain: 4.0,
+            silence_threshold: 0.01,
+        })
+    }
+
+    // runs `seconds` of frames of 10 ms with a constant peak, returns the gain of the last frame
+    fn run(agc: &mut AutoGain, peak: f32, seconds: f32) -> f32 {
+        let mut buffer = Vec::new();
+        for _ in 0..(seconds * 100.0) as usize {
+            buffer = vec![0.0, peak, peak / 2.0];
+            agc.process(&mut buffer, 0.01);
+        }
+        buffer[1] / peak
+    }
+
+    #[test]
+    fn converges_to_the_target() {
+        let mut agc = agc();
+        let gain = run(&mut agc, 0.5, 10.0);
+        assert!((gain - 2.0).abs() < 0.01, "{}", gain);
+
+        let gain = run(&mut agc, 2.0, 2.0);
+        assert!((gain - 0.5).abs() < 0.01, "{}", gain);
+    }
+
+    #[test]
+    fn attacks_faster_than_it_releases() {
+        // lowering the gain for a loud signal
+        let mut loud = agc();
+        let gain = run(&mut loud, 2.0, 0.1);
+        let attack = (1.0 - gain) / (1.0 - 0.5);
+
+        // raising the gain for a quiet one
+        let mut quiet = agc();
+        let gain = run(&mut quiet, 0.5, 0.1);
+        let release = (gain - 1.0) / (2.0 - 1.0);
+
+        // about one time constant of the attack, a tenth of the release
+        assert!((attack - 0.63).abs() < 0.02, "{}", attack);
+        assert!((release - 0.095).abs() < 0.01, "{}", release);
+    }
+
+    #[test]
+    fn gain_is_clamped() {
+        let mut agc = agc();
+        let gain = run(&mut agc, 0.02, 20.0);
+        assert!((gain - 4.0).abs() < 0.01, "{}", gain);
+
+        let gain = run(&mut agc, 100.0, 5.0);
+        assert!((gain - 0.25).abs() < 0.01, "{}", gain);
+    }
+
+    #[test]
+    fn silence_holds_the_gain() {
+        let mut agc = agc();
+        let gain = run(&mut agc, 0.5, 10.0);
+        let held = run(&mut agc, 0.005, 10.0);
+        assert!((held - gain).abs() < 1e-5, "{} {}", held, gain);
+    }
+
+    #[test]
+    fn toggle_bypasses_the_gain() {
+        let mut agc = agc();
+        run(&mut agc, 0.5, 10.0);
+
+        assert!(!agc.toggle());
+        let mut buffer = vec![0.5, 0.25];
+        agc.process(&mut buffer, 0.01);
+        assert_eq!(buffer, [0.5, 0.25]);
+
+        // the gain is kept while disabled
+        assert!(agc.toggle());
+        agc.process(&mut buffer, 0.01);
+        assert!((buffer[0] - 1.0).abs() < 0.01, "{:?}", buffer);
+    }
+}
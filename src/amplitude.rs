@@ -0,0 +1,171 @@
+use crate::config::{Amplitude, AmplitudeMode, Engine, Weighting};
+use crate::notes;
+
+// lowest level that gets converted to decibels, prevents log10(0) = -inf
+const MIN_LEVEL: f32 = 1.0e-10;
+
+/// how the bins of the spectrum are spread over the frequencies
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinScale {
+    // spectrum of audioviz or the Fft engine up to the maximum frequency, see `bin_frequency`
+    Spectrum(f32),
+    // one bin per key of the piano, of the ConstantQ engine
+    Notes,
+}
+
+impl BinScale {
+    /// center frequency of bin `index` of `len` bins
+    pub fn frequency(self, index: usize, len: usize) -> f32 {
+        match self {
+            BinScale::Spectrum(max_frequency) => bin_frequency(index, len, max_frequency),
+            BinScale::Notes => notes::frequency((notes::LOWEST as usize + index) as f32),
+        }
+    }
+}
+
+/// scales every value of the spectrum into a height,
+/// a height of 2.0 spans the whole window
+pub fn scale_buffer(buffer: &mut [f32], amplitude: &Amplitude, bin_scale: BinScale) {
+    let buffer_len = buffer.len();
+    for (i, value) in buffer.iter_mut().enumerate() {
+        *value = match amplitude.mode {
+            AmplitudeMode::Power => {
+                amplitude.volume_amplitude * value.powf(amplitude.volume_factoring)
+            }
+            AmplitudeMode::Decibel => {
+                let frequency = bin_scale.frequency(i, buffer_len);
+                let level = decibel(*value) + weighting(frequency, amplitude.weighting) + amplitude.db_offset;
+
+                normalize_decibel(level, amplitude.db_floor, amplitude.db_ceiling) * 2.0
+            }
+        };
+    }
+}
+
+/// checks that the amplitude mode can be used with the analysis `engine`, done by `Config::validate`
+pub fn validate(amplitude: &Amplitude, engine: Engine) -> Result<(), String> {
+    // the spectrum of audioviz depends on its volume and resolution, so it has no dBFS
+    if amplitude.mode == AmplitudeMode::Decibel && engine == Engine::Audioviz {
+        return Err(String::from(
+            "mode = \"Decibel\" needs calibrated levels, set engine = \"Fft\" or \"ConstantQ\" in [analysis]",
+        ));
+    }
+    Ok(())
+}
+
+/// converts a linear level relative to full scale into dBFS
+pub fn decibel(level: f32) -> f32 {
+    20.0 * level.max(MIN_LEVEL).log10()
+}
+
+/// maps a level in dB between `floor` and `ceiling` to 0.0 - 1.0
+pub fn normalize_decibel(level: f32, floor: f32, ceiling: f32) -> f32 {
+    if ceiling <= floor {
+        return 0.0;
+    }
+    ((level - floor) / (ceiling - floor)).clamp(0.0, 1.0)
+}
+
+/// gain in dB of the A or C weighting curve as defined in IEC 61672-1
+pub fn weighting(frequency: f32, weighting: Weighting) -> f32 {
+    // the curves fall to -inf at 0 Hz
+    let f = frequency.max(1.0) as f64;
+    let f2 = f * f;
+
+    let gain = match weighting {
+        Weighting::None => return 0.0,
+        Weighting::A => {
+            let r = 12194.0_f64.powi(2) * f2 * f2
+                / ((f2 + 20.6_f64.powi(2))
+                    * ((f2 + 107.7_f64.powi(2)) * (f2 + 737.9_f64.powi(2))).sqrt()
+                    * (f2 + 12194.0_f64.powi(2)));
+            20.0 * r.log10() + 2.00
+        }
+        Weighting::C => {
+            let r = 12194.0_f64.powi(2) * f2
+                / ((f2 + 20.6_f64.powi(2)) * (f2 + 12194.0_f64.powi(2)));
+            20.0 * r.log10() + 0.06
+        }
+    };
+    gain as f32
+}
+
+/// approximate center frequency of a bin of the processed spectrum
+///
+/// `audioviz` spreads fft bin `i` of a spectrum with `len` bins onto position `sqrt(i * len)`,
+/// so position `p` roughly corresponds to `max_frequency * (p / len)²`,
+/// this is only exact without frequency scaling and density reduction, and always for the Fft engine
+pub fn bin_frequency(index: usize, len: usize, max_frequency: f32) -> f32 {
+    if len == 0 {
+        return 0.0;
+    }
+    let position = index as f32 / len as f32;
+    position * position * max_frequency
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn assert_near(actual: f32, expected: f32, tolerance: f32) {
+        assert!((actual - expected).abs() < tolerance, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn weighting_curves() {
+        // both are normalized to 0 dB at 1 kHz
+        assert_near(weighting(1000.0, Weighting::A), 0.0, 0.01);
+        assert_near(weighting(1000.0, Weighting::C), 0.0, 0.01);
+        // values of the tables of IEC 61672-1
+        assert_near(weighting(100.0, Weighting::A), -19.1, 0.1);
+        assert_near(weighting(100.0, Weighting::C), -0.3, 0.1);
+        assert_near(weighting(10000.0, Weighting::A), -2.5, 0.1);
+        assert_near(weighting(31.5, Weighting::C), -3.0, 0.1);
+        assert_eq!(weighting(100.0, Weighting::None), 0.0);
+        // 0 Hz is treated as 1 Hz instead of -inf
+        assert!(weighting(0.0, Weighting::A).is_finite());
+    }
+
+    #[test]
+    fn decibels() {
+        assert_eq!(decibel(1.0), 0.0);
+        assert_near(decibel(0.5), -6.02, 0.01);
+        assert_near(decibel(10.0), 20.0, 1e-4);
+        // silence is limited to MIN_LEVEL
+        assert_eq!(decibel(0.0), -200.0);
+        assert_eq!(decibel(-1.0), -200.0);
+    }
+
+    #[test]
+    fn decibels_are_normalized_between_floor_and_ceiling() {
+        assert_eq!(normalize_decibel(-60.0, -60.0, 0.0), 0.0);
+        assert_eq!(normalize_decibel(-30.0, -60.0, 0.0), 0.5);
+        assert_eq!(normalize_decibel(0.0, -60.0, 0.0), 1.0);
+        assert_eq!(normalize_decibel(-90.0, -60.0, 0.0), 0.0);
+        assert_eq!(normalize_decibel(6.0, -60.0, 0.0), 1.0);
+        // an empty range shows nothing instead of dividing by zero
+        assert_eq!(normalize_decibel(-10.0, 0.0, -60.0), 0.0);
+        assert_eq!(normalize_decibel(-10.0, -10.0, -10.0), 0.0);
+    }
+
+    #[test]
+    fn bin_frequencies() {
+        assert_eq!(bin_frequency(0, 100, 20000.0), 0.0);
+        assert_eq!(bin_frequency(50, 100, 20000.0), 5000.0);
+        assert_eq!(bin_frequency(100, 100, 20000.0), 20000.0);
+        assert_eq!(bin_frequency(3, 0, 20000.0), 0.0);
+        assert_eq!(BinScale::Spectrum(20000.0).frequency(50, 100), 5000.0);
+        assert_near(BinScale::Notes.frequency((69 - notes::LOWEST) as usize, 88), 440.0, 1e-3);
+    }
+
+    #[test]
+    fn decibels_need_calibrated_engines() {
+        let amplitude = Amplitude { mode: AmplitudeMode::Decibel, ..Config::default().amplitude };
+        assert!(validate(&amplitude, Engine::Audioviz).is_err());
+        assert!(validate(&amplitude, Engine::Fft).is_ok());
+        assert!(validate(&amplitude, Engine::ConstantQ).is_ok());
+        let amplitude = Amplitude { mode: AmplitudeMode::Power, ..amplitude };
+        assert!(validate(&amplitude, Engine::Audioviz).is_ok());
+    }
+}
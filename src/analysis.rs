@@ -0,0 +1,503 @@
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicU16, AtomicU32, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use crate::config::{self, Engine, SpectrumScale, WindowFunction};
+use crate::notes;
+
+// more bands than pixels would only cost time
+const MAX_BANDS: usize = 1024;
+
+/// sample rate and channels of the captured samples, set by the audio thread for every stream
+#[derive(Debug, Default)]
+pub struct StreamFormat {
+    sample_rate: AtomicU32,
+    channels: AtomicU16,
+}
+
+impl StreamFormat {
+    pub fn set(&self, sample_rate: u32, channels: u16) {
+        self.sample_rate.store(sample_rate, Ordering::Relaxed);
+        self.channels.store(channels, Ordering::Relaxed);
+    }
+
+    /// sample rate and channels, a sample rate of 0 until the first stream was opened
+    pub fn get(&self) -> (u32, u16) {
+        (self.sample_rate.load(Ordering::Relaxed), self.channels.load(Ordering::Relaxed))
+    }
+}
+
+/// spectrum analysis of audiovis, a replacement of `audioviz::AudioStream` with the same events
+///
+/// the spectrum is spread over the bands like the one of audioviz, band `p` of `len` bands
+/// is centered at `max_frequency * (p / len)²`, see `amplitude::bin_frequency`,
+/// but the levels are calibrated and independent of `volume`: a full scale sine at the frequency
+/// of an fft bin is 1.0, between two bins it is lower by the scalloping of the window, except for FlatTop
+pub struct AnalysisStream {
+    event_sender: mpsc::Sender<audioviz::Event>,
+}
+
+impl AnalysisStream {
+    pub fn init(analysis: config::Analysis, config: audioviz::Config, format: Arc<StreamFormat>) -> Self {
+        let (event_sender, events) = mpsc::channel();
+
+        thread::spawn(move || {
+            let mut analyser = Analyser::new(analysis, config, format);
+            for event in events.iter() {
+                analyser.handle(event);
+            }
+        });
+
+        let refresh_sender = event_sender.clone();
+        let interval = Duration::from_secs_f32(1.0 / config.refresh_rate.max(1) as f32);
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            if refresh_sender.send(audioviz::Event::RequestRefresh).is_err() {
+                return;
+            }
+        });
+
+        Self { event_sender }
+    }
+
+    pub fn get_event_sender(&self) -> mpsc::Sender<audioviz::Event> {
+        self.event_sender.clone()
+    }
+}
+
+struct Analyser {
+    config: audioviz::Config,
+    engine: Engine,
+    scale: SpectrumScale,
+    format: Arc<StreamFormat>,
+    // format of the buffered samples
+    sample_rate: u32,
+    channels: u16,
+    fft: Fft,
+    // created for the sample rate of the first samples
+    constant_q: Option<ConstantQ>,
+    // samples between two ffts
+    hop: usize,
+    // interleaved samples of incomplete frames
+    interleaved: Vec<f32>,
+    // downmixed samples that were not analysed yet
+    samples: Vec<f32>,
+    spectrum: Vec<f32>,
+    // sum of the spectra since the last refresh, unused by ConstantQ
+    sum: Vec<f32>,
+    // ffts since the last refresh, or for ConstantQ pushes of samples
+    count: usize,
+    // bands of the last refreshes, the newest last
+    history: VecDeque<Vec<f32>>,
+    smoothed: Vec<f32>,
+}
+
+impl Analyser {
+    fn new(analysis: config::Analysis, config: audioviz::Config, format: Arc<StreamFormat>) -> Self {
+        let hop = ((analysis.fft_size as f32 * (1.0 - analysis.overlap)) as usize).max(1);
+        Self {
+            config,
+            engine: analysis.engine,
+            scale: analysis.scale,
+            format,
+            sample_rate: 0,
+            channels: 0,
+            fft: Fft::new(analysis),
+            constant_q: None,
+            hop,
+            interleaved: Vec::new(),
+            samples: Vec::new(),
+            spectrum: Vec::new(),
+            sum: Vec::new(),
+            count: 0,
+            history: VecDeque::new(),
+            smoothed: Vec::new(),
+        }
+    }
+
+    fn handle(&mut self, event: audioviz::Event) {
+        match event {
+            audioviz::Event::SendData(data) => self.push(&data),
+            audioviz::Event::RequestData(sender) => {
+                sender.send(self.smoothed.clone()).ok();
+            }
+            audioviz::Event::RequestRefresh => self.refresh(),
+            audioviz::Event::RequestConfig(sender) => {
+                sender.send(self.config).ok();
+            }
+            audioviz::Event::SendConfig(config) => self.config = config,
+            audioviz::Event::ClearBuffer => self.clear(),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.sum.clear();
+        self.count = 0;
+        self.history.clear();
+    }
+
+    fn push(&mut self, data: &[f32]) {
+        let (sample_rate, channels) = self.format.get();
+        if sample_rate == 0 || channels == 0 {
+            return;
+        }
+        // samples of another device
+        if (sample_rate, channels) != (self.sample_rate, self.channels) {
+            self.sample_rate = sample_rate;
+            self.channels = channels;
+            self.interleaved.clear();
+            self.samples.clear();
+            self.clear();
+            if self.engine == Engine::ConstantQ {
+                self.constant_q = Some(ConstantQ::new(sample_rate, self.scale));
+            }
+        }
+
+        // mono, so that a sine on every channel keeps its level
+        self.interleaved.extend_from_slice(data);
+        let frames = self.interleaved.len() / channels as usize;
+        for frame in self.interleaved.chunks_exact(channels as usize) {
+            self.samples.push(frame.iter().sum::<f32>() / channels as f32);
+        }
+        self.interleaved.drain(..frames * channels as usize);
+
+        // analysed at every refresh, only the samples of the longest kernel are needed
+        if let Some(constant_q) = &self.constant_q {
+            let excess = self.samples.len().saturating_sub(constant_q.length);
+            self.samples.drain(..excess);
+            self.count += 1;
+            return;
+        }
+
+        let size = self.fft.size;
+        let mut start = 0;
+        while self.samples.len() - start >= size {
+            self.fft.process(&self.samples[start..start + size], &mut self.spectrum);
+            if self.sum.len() != self.spectrum.len() {
+                self.sum = vec![0.0; self.spectrum.len()];
+                self.count = 0;
+            }
+            for (sum, value) in self.sum.iter_mut().zip(self.spectrum.iter()) {
+                *sum += value;
+            }
+            self.count += 1;
+            start += self.hop;
+        }
+        self.samples.drain(..start);
+    }
+
+    // averages the ffts since the last refresh into bands and smooths them over the last refreshes
+    fn refresh(&mut self) {
+        if self.count > 0 {
+            let bands = match &self.constant_q {
+                Some(constant_q) => constant_q.process(&self.samples).collect(),
+                None => {
+                    let count = self.count as f32;
+                    let average: Vec<f32> = self.sum.iter().map(|sum| sum / count).collect();
+                    self.bands(&average)
+                }
+            };
+            self.history.push_back(bands);
+            while self.history.len() > self.config.buffering.max(1) {
+                self.history.pop_front();
+            }
+            self.sum.iter_mut().for_each(|sum| *sum = 0.0);
+            self.count = 0;
+        }
+
+        // newer spectra weigh more, like the buffering of audioviz
+        let len = match self.history.back() {
+            Some(newest) => newest.len(),
+            None => return,
+        };
+        let mut smoothed = vec![0.0; len];
+        let mut total = 0.0;
+        for (i, bands) in self.history.iter().enumerate() {
+            let weight = (i + 1) as f32;
+            total += weight;
+            for (smoothed, value) in smoothed.iter_mut().zip(bands.iter()) {
+                *smoothed += value * weight;
+            }
+        }
+        smoothed.iter_mut().for_each(|value| *value /= total);
+        self.smoothed = smoothed;
+    }
+
+    // peak of the bins of every band, interpolated for bands between two bins
+    fn bands(&self, spectrum: &[f32]) -> Vec<f32> {
+        bands(spectrum, self.sample_rate, self.fft.padded_size, self.config.max_frequency)
+    }
+}
+
+// bands of a spectrum of `padded_size` samples, band `p` reaches from half a band below
+// to half a band above its center at `max_frequency * (p / len)²`
+fn bands(spectrum: &[f32], sample_rate: u32, padded_size: usize, max_frequency: usize) -> Vec<f32> {
+    let bin_width = sample_rate as f32 / padded_size as f32;
+    let max_frequency = (max_frequency as f32).min(sample_rate as f32 / 2.0);
+    let len = ((max_frequency / bin_width) as usize).min(MAX_BANDS);
+    let frequency = |p: f32| max_frequency * (p.max(0.0) / len as f32).powi(2);
+
+    (0..len)
+        .map(|p| {
+            let low = frequency(p as f32 - 0.5) / bin_width;
+            let high = frequency(p as f32 + 0.5) / bin_width;
+            let first = low.ceil() as usize;
+            let last = (high.ceil() as usize).min(spectrum.len());
+            if first < last {
+                spectrum[first..last].iter().fold(0.0_f32, |peak, value| peak.max(*value))
+            } else {
+                let center = frequency(p as f32) / bin_width;
+                let i = (center.floor() as usize).min(spectrum.len() - 2);
+                let t = (center - i as f32).clamp(0.0, 1.0);
+                spectrum[i] * (1.0 - t) + spectrum[i + 1] * t
+            }
+        })
+        .collect()
+}
+
+struct Fft {
+    fft: Arc<dyn rustfft::Fft<f32>>,
+    // samples per fft and the size after zero padding
+    size: usize,
+    padded_size: usize,
+    window: Vec<f32>,
+    // turns magnitudes into the amplitudes of sines
+    gain: f32,
+    scale: SpectrumScale,
+    buffer: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+}
+
+impl Fft {
+    fn new(analysis: config::Analysis) -> Self {
+        let size = analysis.fft_size;
+        let padded_size = size * analysis.zero_padding.max(1);
+        let fft = FftPlanner::new().plan_fft_forward(padded_size);
+        let window = window(analysis.window, size);
+        let gain = 2.0 / window.iter().sum::<f32>();
+        let scratch = vec![Complex::default(); fft.get_inplace_scratch_len()];
+
+        Self {
+            fft,
+            size,
+            padded_size,
+            window,
+            gain,
+            scale: analysis.scale,
+            buffer: vec![Complex::default(); padded_size],
+            scratch,
+        }
+    }
+
+    // level of every bin from 0 hz up to half the sample rate of `size` samples
+    fn process(&mut self, samples: &[f32], spectrum: &mut Vec<f32>) {
+        for (i, value) in self.buffer.iter_mut().enumerate() {
+            *value = match self.window.get(i) {
+                Some(window) => Complex::new(samples[i] * window, 0.0),
+                None => Complex::default(),
+            };
+        }
+        self.fft.process_with_scratch(&mut self.buffer, &mut self.scratch);
+
+        spectrum.clear();
+        spectrum.extend(self.buffer[..=self.padded_size / 2].iter().map(|value| {
+            let amplitude = value.norm() * self.gain;
+            match self.scale {
+                SpectrumScale::Magnitude => amplitude,
+                SpectrumScale::Power => amplitude * amplitude,
+            }
+        }));
+    }
+}
+
+// one windowed complex sinusoid per key of the piano, its length grows towards the bass,
+// so that every key gets separated from its neighbours
+struct ConstantQ {
+    kernels: Vec<Kernel>,
+    // samples of the longest kernel
+    length: usize,
+    scale: SpectrumScale,
+}
+
+struct Kernel {
+    cos: Vec<f32>,
+    sin: Vec<f32>,
+    // turns the magnitude into the amplitude of a sine
+    gain: f32,
+}
+
+impl ConstantQ {
+    fn new(sample_rate: u32, scale: SpectrumScale) -> Self {
+        // center frequency divided by the bandwidth of a semitone
+        let q = 1.0 / (2.0_f32.powf(1.0 / 12.0) - 1.0);
+
+        let kernels: Vec<Kernel> = (notes::LOWEST..=notes::HIGHEST)
+            .map(|note| {
+                let frequency = notes::frequency(note as f32);
+                let length = (q * sample_rate as f32 / frequency).ceil() as usize;
+                let window = window(WindowFunction::Hann, length);
+                let phase = |n: usize| 2.0 * PI * frequency * n as f32 / sample_rate as f32;
+                Kernel {
+                    cos: window.iter().enumerate().map(|(n, w)| w * phase(n).cos()).collect(),
+                    sin: window.iter().enumerate().map(|(n, w)| w * phase(n).sin()).collect(),
+                    gain: 2.0 / window.iter().sum::<f32>(),
+                }
+            })
+            .collect();
+        let length = kernels.iter().map(|kernel| kernel.cos.len()).max().unwrap_or(0);
+
+        Self { kernels, length, scale }
+    }
+
+    // level of every key over the newest samples, 0.0 for keys that need more samples than there are
+    fn process<'a>(&'a self, samples: &'a [f32]) -> impl Iterator<Item = f32> + 'a {
+        self.kernels.iter().map(move |kernel| {
+            let length = kernel.cos.len();
+            if samples.len() < length {
+                return 0.0;
+            }
+            let samples = &samples[samples.len() - length..];
+            let re: f32 = samples.iter().zip(kernel.cos.iter()).map(|(s, k)| s * k).sum();
+            let im: f32 = samples.iter().zip(kernel.sin.iter()).map(|(s, k)| s * k).sum();
+            let amplitude = re.hypot(im) * kernel.gain;
+            match self.scale {
+                SpectrumScale::Magnitude => amplitude,
+                SpectrumScale::Power => amplitude * amplitude,
+            }
+        })
+    }
+}
+
+// periodic window, sums of cosines with these coefficients
+fn window(function: WindowFunction, size: usize) -> Vec<f32> {
+    let coefficients: &[f32] = match function {
+        WindowFunction::Hann => &[0.5, 0.5],
+        WindowFunction::BlackmanHarris => &[0.35875, 0.48829, 0.14128, 0.01168],
+        WindowFunction::FlatTop => &[0.21557895, 0.41663158, 0.27726316, 0.08357895, 0.006947368],
+    };
+    (0..size)
+        .map(|n| {
+            let x = 2.0 * PI * n as f32 / size as f32;
+            coefficients
+                .iter()
+                .enumerate()
+                .map(|(k, a)| {
+                    let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+                    sign * a * (k as f32 * x).cos()
+                })
+                .sum()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amplitude::bin_frequency;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    fn sine(frequency: f32, amplitude: f32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|n| amplitude * (2.0 * PI * frequency * n as f32 / SAMPLE_RATE as f32).sin())
+            .collect()
+    }
+
+    fn analysis(engine: Engine, window: WindowFunction) -> config::Analysis {
+        config::Analysis {
+            engine,
+            window,
+            ..crate::Config::default().analysis
+        }
+    }
+
+    // index and level of the highest value
+    fn peak(values: &[f32]) -> (usize, f32) {
+        values.iter().enumerate().fold((0, 0.0), |peak, (i, value)| if *value > peak.1 { (i, *value) } else { peak })
+    }
+
+    #[test]
+    fn full_scale_sine_at_a_bin_is_one() {
+        let mut fft = Fft::new(analysis(Engine::Fft, WindowFunction::Hann));
+        let frequency = 85.0 * SAMPLE_RATE as f32 / fft.size as f32;
+        let mut spectrum = Vec::new();
+        fft.process(&sine(frequency, 1.0, fft.size), &mut spectrum);
+
+        let (bin, level) = peak(&spectrum);
+        assert_eq!(bin, 85);
+        assert!((level - 1.0).abs() < 0.01, "level {}", level);
+    }
+
+    #[test]
+    fn flat_top_is_exact_between_bins() {
+        let mut fft = Fft::new(analysis(Engine::Fft, WindowFunction::FlatTop));
+        let mut spectrum = Vec::new();
+        fft.process(&sine(1000.0, 0.5, fft.size), &mut spectrum);
+
+        let (bin, level) = peak(&spectrum);
+        let bin_width = SAMPLE_RATE as f32 / fft.padded_size as f32;
+        assert!((bin as f32 * bin_width - 1000.0).abs() <= bin_width);
+        assert!((level - 0.5).abs() < 0.005, "level {}", level);
+    }
+
+    #[test]
+    fn power_scale_and_zero_padding() {
+        let mut fft = Fft::new(config::Analysis {
+            scale: SpectrumScale::Power,
+            zero_padding: 4,
+            ..analysis(Engine::Fft, WindowFunction::Hann)
+        });
+        let frequency = 100.0 * SAMPLE_RATE as f32 / fft.size as f32;
+        let mut spectrum = Vec::new();
+        fft.process(&sine(frequency, 0.5, fft.size), &mut spectrum);
+
+        assert_eq!(spectrum.len(), fft.size * 4 / 2 + 1);
+        let (bin, level) = peak(&spectrum);
+        assert_eq!(bin, 400);
+        assert!((level - 0.25).abs() < 0.005, "level {}", level);
+    }
+
+    #[test]
+    fn analyser_places_sine_in_its_band_independent_of_volume() {
+        let format = Arc::new(StreamFormat::default());
+        format.set(SAMPLE_RATE, 2);
+        let mut audio = crate::Config::default().audio.to_audioviz();
+        audio.volume = 0.5;
+        let mut analyser = Analyser::new(analysis(Engine::Fft, WindowFunction::FlatTop), audio, format);
+
+        let interleaved: Vec<f32> = sine(1000.0, 1.0, SAMPLE_RATE as usize).iter().flat_map(|s| [*s, *s]).collect();
+        analyser.handle(audioviz::Event::SendData(interleaved));
+        analyser.handle(audioviz::Event::RequestRefresh);
+
+        let (band, level) = peak(&analyser.smoothed);
+        let frequency = bin_frequency(band, analyser.smoothed.len(), audio.max_frequency as f32);
+        let bin_width = SAMPLE_RATE as f32 / analyser.fft.padded_size as f32;
+        assert!((frequency - 1000.0).abs() <= bin_width, "band at {} hz", frequency);
+        assert!((level - 1.0).abs() < 0.01, "level {}", level);
+    }
+
+    #[test]
+    fn constant_q_levels_of_keys() {
+        let constant_q = ConstantQ::new(SAMPLE_RATE, SpectrumScale::Magnitude);
+        for note in [21_u8, 69, 108] {
+            let samples = sine(notes::frequency(note as f32), 0.5, constant_q.length);
+            let levels: Vec<f32> = constant_q.process(&samples).collect();
+
+            let (key, level) = peak(&levels);
+            assert_eq!(key, (note - notes::LOWEST) as usize);
+            assert!((level - 0.5).abs() < 0.01, "level {} of note {}", level, note);
+        }
+    }
+
+    #[test]
+    fn windows_are_periodic() {
+        let hann = window(WindowFunction::Hann, 8);
+        assert_eq!(hann[0], 0.0);
+        assert!((hann[4] - 1.0).abs() < 1e-6);
+        assert!((hann.iter().sum::<f32>() - 4.0).abs() < 1e-5);
+    }
+}
@@ -1,107 +1,681 @@
 use gag::Gag;
 use std::thread;
-use std::sync::mpsc;
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use colored::*;
+use crate::idle::Activity;
+use crate::beat::{BeatDetector, BeatEvents};
+use crate::analysis::{AnalysisStream, StreamFormat};
+use crate::pitch::{PitchDetector, PitchState};
+use crate::meter::{Meter, MeterState};
+use crate::config::{self, Config, Engine};
+use crate::ring;
+use crate::graphics::scene::Inputs;
 
-#[derive(Debug, Clone, Copy)]
+pub use crate::ring::Stats as RingStats;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum AudioDevice {
     Input(usize),
     Output(usize),
+    // monitor of the default sink, needs `monitor::select_source` first
+    Monitor,
 }
 
-pub fn init_audio_sender(event_sender: mpsc::Sender<audioviz::Event>, audio_device: AudioDevice) {
-    thread::spawn(move || {
-        // dont print any alsa or jack errors on *nix systems to stderr
-        let _print_gag = Gag::stderr().unwrap();
-
-        let host = cpal::default_host();
-        let input_devices = host.input_devices().unwrap().collect::<Vec<cpal::Device>>();
-        let output_devices = host.output_devices().unwrap().collect::<Vec<cpal::Device>>();
+#[derive(Debug)]
+pub enum AudioError {
+    Devices(cpal::DevicesError),
+    // kind of device, requested index and amount of available devices
+    NoSuchDevice(&'static str, usize, usize),
+    NoDevices,
+    // why there is no device that captures the system output
+    NoMonitor(String),
+    DefaultConfig(cpal::DefaultStreamConfigError),
+    UnsupportedSampleFormat(cpal::SampleFormat),
+    BuildStream(cpal::BuildStreamError),
+    PlayStream(cpal::PlayStreamError),
+    // the audio thread stopped before reporting whether capturing started
+    Disconnected,
+    // reason why the configuration can not be used, see `Config::validate`
+    InvalidConfig(String),
+}
 
-        let device = match audio_device {
-            AudioDevice::Input(i) => {
-                &input_devices[i]
+impl std::fmt::Display for AudioError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AudioError::Devices(e) => write!(f, "failed to list audio devices: {}", e),
+            AudioError::NoSuchDevice(kind, index, 0) => {
+                write!(f, "there is no {} device {}, no {} devices are available", kind, index, kind)
             }
-            AudioDevice::Output(i) => {
-                &output_devices[i]
+            AudioError::NoSuchDevice(kind, index, available) => write!(
+                f,
+                "there is no {} device {}, available are 0 to {}, see --iter-devices",
+                kind,
+                index,
+                available - 1,
+            ),
+            AudioError::NoMonitor(reason) => write!(f, "no monitor source to capture the system output, {}", reason),
+            AudioError::NoDevices => write!(f, "no audio devices are available"),
+            AudioError::DefaultConfig(e) => write!(f, "failed to get the configuration of the device: {}", e),
+            AudioError::UnsupportedSampleFormat(format) => write!(f, "unsupported sample format {:?}", format),
+            AudioError::BuildStream(e) => write!(f, "failed to open the audio stream: {}", e),
+            AudioError::PlayStream(e) => write!(f, "failed to start the audio stream: {}", e),
+            AudioError::Disconnected => write!(f, "the audio thread stopped unexpectedly"),
+            AudioError::InvalidConfig(reason) => write!(f, "invalid configuration: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for AudioError {}
+
+/// captures the audio of a device and analyses it,
+/// everything a visualiser needs as input
+pub struct AudioSource {
+    // to the analysis of audioviz or audiovis, both understand the events of audioviz
+    event_sender: mpsc::Sender<audioviz::Event>,
+    pub activity: Arc<Activity>,
+    pub beat_events: Arc<BeatEvents>,
+    // overruns of the buffer between the audio callback and the analysis
+    pub ring_stats: Arc<RingStats>,
+    // fundamental frequency, only detected while a renderer activates it
+    pub pitch: Arc<PitchState>,
+    // peak, rms and loudness, only measured while a renderer activates them
+    pub meters: Arc<MeterState>,
+    devices: DeviceSwitch,
+}
+
+impl AudioSource {
+    pub fn new(audio_device: AudioDevice, config: &Config) -> Result<Self, AudioError> {
+        // the fields are public, so they could have been changed after parsing
+        config.validate().map_err(AudioError::InvalidConfig)?;
+        let format = Arc::new(StreamFormat::default());
+        let event_sender = match config.analysis.engine {
+            Engine::Audioviz => audioviz::AudioStream::init(config.audio.to_audioviz()).get_event_sender(),
+            Engine::Fft | Engine::ConstantQ => {
+                AnalysisStream::init(config.analysis, config.audio.to_audioviz(), format.clone()).get_event_sender()
             }
         };
+        let activity = Arc::new(Activity::new(config.idle));
+        let beat_events = Arc::new(BeatEvents::new());
+        let ring_stats = Arc::new(RingStats::default());
+        let pitch = Arc::new(PitchState::default());
+        let meters = Arc::new(MeterState::default());
+        let consumers = Consumers {
+            event_sender: event_sender.clone(),
+            activity: activity.clone(),
+            beat_config: config.beat,
+            beat_events: beat_events.clone(),
+            ring_stats: ring_stats.clone(),
+            pitch: pitch.clone(),
+            tuner: config.tuner,
+            meters: meters.clone(),
+            meter_config: config.meters,
+            format,
+        };
+        let devices = init_audio_sender(audio_device, consumers, config.reconnect)?;
 
-        match audio_device {
-            AudioDevice::Input(_) => {
-                println!("using input device: {}", device.name().unwrap().green().bold());
-            }
-            AudioDevice::Output(_) => {
-                println!("using output device: {}", device.name().unwrap().green().bold());
-            }
+        Ok(Self {
+            event_sender,
+            activity,
+            beat_events,
+            ring_stats,
+            pitch,
+            meters,
+            devices,
+        })
+    }
+
+    /// sender to request the spectrum from or to change the configuration of audioviz
+    pub fn event_sender(&self) -> mpsc::Sender<audioviz::Event> {
+        self.event_sender.clone()
+    }
+
+    /// handle to change the captured device while running
+    pub fn device_switch(&self) -> DeviceSwitch {
+        self.devices.clone()
+    }
+
+    /// everything a `Scene` or `Visualiser` reads from the audio, subscribes to the beats
+    pub fn inputs(&self) -> Inputs {
+        Inputs {
+            event_sender: self.event_sender(),
+            activity: Some(self.activity.clone()),
+            beats: Some(self.beat_events.subscribe()),
+            pitch: Some(self.pitch.clone()),
+            meters: Some(self.meters.clone()),
+            devices: Some(self.device_switch()),
         }
+    }
+}
 
-        //let device_config =  device.default_input_config().unwrap();
-        let device_config = match audio_device {
-            AudioDevice::Input(_) => {
-                device.default_input_config().unwrap()
-            }
-            AudioDevice::Output(_) => {
-                device.default_output_config().unwrap()
-            }
-        };
+/// changes the device the audio thread captures from,
+/// the analysis of audioviz and everything listening to it keep running
+#[derive(Clone)]
+pub struct DeviceSwitch {
+    events: mpsc::Sender<StreamEvent>,
+}
+
+impl DeviceSwitch {
+    /// opens `device` and returns its name, goes back to the current device if that fails,
+    /// blocks until the audio thread is done, see `cycle` for switching in the background
+    pub fn switch(&self, device: AudioDevice) -> Result<String, AudioError> {
+        self.request(Target::Device(device)).recv().unwrap_or(Err(AudioError::Disconnected))
+    }
+
+    /// switches to the device after the current one in the order of --iter-devices,
+    /// the input devices first, on the audio thread, the receiver gets the name once it is open
+    pub fn cycle(&self) -> mpsc::Receiver<Result<String, AudioError>> {
+        self.request(Target::Next)
+    }
 
-        let stream = match device_config.sample_format() {
-            cpal::SampleFormat::F32 => device.build_input_stream(
-                &device_config.into(),
-                move |data, _: &_| handle_input_data_f32(data, event_sender.clone()),
-                err_fn,
-            ).unwrap(),
-            other => {
-                panic!("Unsupported sample format {:?}", other);
+    fn request(&self, target: Target) -> mpsc::Receiver<Result<String, AudioError>> {
+        let (tx, rx) = mpsc::channel();
+        // if the audio thread stopped, the receiver gets disconnected
+        self.events.send(StreamEvent::Switch(target, tx)).ok();
+        rx
+    }
+}
+
+// everything the audio thread waits for
+enum StreamEvent {
+    // error of the stream with the given generation, errors of replaced streams get ignored
+    Error(u64, cpal::StreamError),
+    Switch(Target, mpsc::Sender<Result<String, AudioError>>),
+}
+
+// device a switch goes to
+enum Target {
+    Device(AudioDevice),
+    // the one after the current device in the order of --iter-devices
+    Next,
+}
+
+// stream of the captured device and the thread analysing its samples
+struct Running {
+    stream: cpal::Stream,
+    analysis: thread::JoinHandle<()>,
+}
+
+impl Running {
+    // stops capturing and waits until the last samples are analysed,
+    // so that they never get mixed up with the samples or the format of the next stream
+    fn stop(self) {
+        drop(self.stream);
+        self.analysis.join().ok();
+    }
+}
+
+// seconds of samples the buffer between the audio callback and the analysis can hold
+const RING_SECONDS: usize = 1;
+// how often the analysis thread looks for new samples
+const ANALYSIS_INTERVAL: Duration = Duration::from_millis(2);
+// minimum time between two reports of dropped samples
+const OVERRUN_REPORT_INTERVAL: Duration = Duration::from_secs(5);
+// samples of input devices have to arrive at least this often while reconnecting is enabled,
+// otherwise the device counts as disconnected
+const STALL_TIMEOUT: Duration = Duration::from_secs(2);
+// first delay between attempts to reconnect, doubles after every failed attempt
+const MIN_RECONNECT_DELAY: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DeviceKind {
+    Input,
+    Output,
+}
+
+impl DeviceKind {
+    fn name(self) -> &'static str {
+        match self {
+            DeviceKind::Input => "input",
+            DeviceKind::Output => "output",
+        }
+    }
+}
+
+// everything the captured samples get passed to
+#[derive(Clone)]
+struct Consumers {
+    event_sender: mpsc::Sender<audioviz::Event>,
+    activity: Arc<Activity>,
+    beat_config: config::Beat,
+    beat_events: Arc<BeatEvents>,
+    ring_stats: Arc<RingStats>,
+    pitch: Arc<PitchState>,
+    tuner: config::Tuner,
+    meters: Arc<MeterState>,
+    meter_config: config::Meters,
+    format: Arc<StreamFormat>,
+}
+
+/// starts capturing on its own thread and waits until the stream is playing or failed to start,
+/// afterwards the thread reopens the device if it gets disconnected and switches between devices
+fn init_audio_sender(
+    audio_device: AudioDevice,
+    consumers: Consumers,
+    reconnect: config::Reconnect,
+) -> Result<DeviceSwitch, AudioError> {
+    let (result_sender, result_receiver) = mpsc::channel();
+    let (stream_events, events) = mpsc::channel();
+    let devices = DeviceSwitch {
+        events: stream_events.clone(),
+    };
+
+    // the stream can not be sent to another thread, so it lives on this one
+    thread::spawn(move || {
+        let mut generation = 0;
+        let opened = find_device(audio_device).and_then(|(device, kind)| {
+            let stream = open_stream(&device, kind, consumers.clone(), stream_events.clone(), generation)?;
+            Ok((device, kind, stream))
+        });
+        let (device, mut kind, stream) = match opened {
+            Ok(opened) => opened,
+            Err(e) => {
+                result_sender.send(Err(e)).ok();
+                return;
             }
         };
+        result_sender.send(Ok(())).ok();
+        println!("using {} device: {}", kind.name(), device_name(&device).green().bold());
 
-        stream.play().unwrap();
+        let mut name = device_name(&device);
+        let mut stream = Some(stream);
+        // time of the next attempt to reconnect and the delay after it
+        let mut retry: Option<(Instant, f32)> = None;
+        loop {
+            let timeout = match retry {
+                Some((at, _)) => at.saturating_duration_since(Instant::now()),
+                None => STALL_TIMEOUT,
+            };
+            // waits for errors of the stream or for it to stop delivering samples
+            match events.recv_timeout(timeout) {
+                Ok(StreamEvent::Error(g, e)) => {
+                    if g != generation || stream.is_none() {
+                        continue;
+                    }
+                    eprintln!("{} {}", "audio stream failed:".red().bold(), e);
+                }
+                Ok(StreamEvent::Switch(target, reply)) => {
+                    let audio_device = match target {
+                        Target::Device(audio_device) => audio_device,
+                        Target::Next => match list_audio_devices().map(|devices| next_device(&devices, kind, &name)) {
+                            Ok(Some(audio_device)) => audio_device,
+                            // reopening the only device would just interrupt it
+                            Ok(None) => {
+                                reply.send(Ok(name.clone())).ok();
+                                continue;
+                            }
+                            Err(e) => {
+                                reply.send(Err(e)).ok();
+                                continue;
+                            }
+                        },
+                    };
 
-        // parks the thread so stream.play() does not get dropped and stops
-        thread::park();
+                    let stopped = stream.take().map(Running::stop).is_some();
+                    generation += 1;
+                    let opened = find_device(audio_device).and_then(|(device, kind)| {
+                        let stream = open_stream(&device, kind, consumers.clone(), stream_events.clone(), generation)?;
+                        Ok((device, kind, stream))
+                    });
+                    match opened {
+                        Ok((device, new_kind, new_stream)) => {
+                            stream = Some(new_stream);
+                            kind = new_kind;
+                            name = device_name(&device);
+                            retry = None;
+                            consumers.activity.set_connected(true);
+                            println!("switched to {} device: {}", kind.name(), name.green().bold());
+                            reply.send(Ok(name.clone())).ok();
+                            continue;
+                        }
+                        Err(e) => {
+                            eprintln!("{} {}", "failed to switch the audio device:".red().bold(), e);
+                            reply.send(Err(e)).ok();
+                            // a device that was already disconnected keeps waiting for its next attempt
+                            if !stopped {
+                                continue;
+                            }
+                            if let Some((_, previous)) = reopen_device(&name, kind, reconnect, &consumers, &stream_events, generation) {
+                                stream = Some(previous);
+                                continue;
+                            }
+                        }
+                    }
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    if let Some((_, delay)) = retry {
+                        generation += 1;
+                        match reopen_device(&name, kind, reconnect, &consumers, &stream_events, generation) {
+                            Some((device, new_stream)) => {
+                                stream = Some(new_stream);
+                                name = device_name(&device);
+                                retry = None;
+                                consumers.activity.set_connected(true);
+                                println!("reconnected to {} device: {}", kind.name(), name.green().bold());
+                            }
+                            None => {
+                                let next_delay = (delay * 2.0).min(reconnect.max_delay.max(MIN_RECONNECT_DELAY));
+                                retry = Some((Instant::now() + Duration::from_secs_f32(delay), next_delay));
+                            }
+                        }
+                        continue;
+                    }
+                    // loopback of output devices delivers nothing during silence, so only errors count there,
+                    // and without reconnecting a stall might still recover on its own
+                    let detects_stalls = reconnect.enabled && kind == DeviceKind::Input;
+                    if stream.is_none() || !detects_stalls || consumers.activity.since_last_capture() < STALL_TIMEOUT {
+                        continue;
+                    }
+                    eprintln!("{} {}", "audio stream stopped:".red().bold(), name);
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => return,
+            }
+
+            if let Some(running) = stream.take() {
+                running.stop();
+            }
+            consumers.activity.set_connected(false);
+            if reconnect.enabled {
+                let delay = Duration::from_secs_f32(MIN_RECONNECT_DELAY);
+                retry = Some((Instant::now() + delay, MIN_RECONNECT_DELAY * 2.0));
+            }
+        }
     });
+
+    result_receiver.recv().unwrap_or(Err(AudioError::Disconnected))?;
+    Ok(devices)
+}
+
+// tries to open the device with `name` once, or the default device if it is gone
+fn reopen_device(
+    name: &str,
+    kind: DeviceKind,
+    reconnect: config::Reconnect,
+    consumers: &Consumers,
+    stream_events: &mpsc::Sender<StreamEvent>,
+    generation: u64,
+) -> Option<(cpal::Device, Running)> {
+    let device = match find_device_by_name(name, kind) {
+        Some(device) => device,
+        None if reconnect.fallback_to_default => default_device(kind)?,
+        None => return None,
+    };
+    match open_stream(&device, kind, consumers.clone(), stream_events.clone(), generation) {
+        Ok(stream) => Some((device, stream)),
+        Err(e) => {
+            eprintln!("{} {}", "failed to reconnect:".red().bold(), e);
+            None
+        }
+    }
+}
+
+fn find_device(audio_device: AudioDevice) -> Result<(cpal::Device, DeviceKind), AudioError> {
+    // dont print any alsa or jack errors on *nix systems to stderr while listing the devices,
+    // fails if stderr is already gagged somewhere else, which is harmless
+    let _print_gag = Gag::stderr().ok();
+
+    let host = cpal::default_host();
+    let (devices, index, kind) = match audio_device {
+        AudioDevice::Input(i) => (host.input_devices(), i, DeviceKind::Input),
+        AudioDevice::Output(i) => (host.output_devices(), i, DeviceKind::Output),
+        AudioDevice::Monitor => return Ok((crate::monitor::find_device()?, DeviceKind::Input)),
+    };
+    let mut devices = devices.map_err(AudioError::Devices)?.collect::<Vec<cpal::Device>>();
+
+    check_index(kind, index, devices.len())?;
+    Ok((devices.swap_remove(index), kind))
+}
+
+// whether a device index exists among the available devices of a kind
+fn check_index(kind: DeviceKind, index: usize, available: usize) -> Result<(), AudioError> {
+    if index >= available {
+        return Err(AudioError::NoSuchDevice(kind.name(), index, available));
+    }
+    Ok(())
+}
+
+fn find_device_by_name(name: &str, kind: DeviceKind) -> Option<cpal::Device> {
+    let _print_gag = Gag::stderr().ok();
+
+    let host = cpal::default_host();
+    let mut devices = match kind {
+        DeviceKind::Input => host.input_devices().ok()?,
+        DeviceKind::Output => host.output_devices().ok()?,
+    };
+    devices.find(|device| device.name().map(|n| n == name).unwrap_or(false))
+}
+
+fn default_device(kind: DeviceKind) -> Option<cpal::Device> {
+    let _print_gag = Gag::stderr().ok();
+
+    let host = cpal::default_host();
+    match kind {
+        DeviceKind::Input => host.default_input_device(),
+        DeviceKind::Output => host.default_output_device(),
+    }
+}
+
+fn device_name(device: &cpal::Device) -> String {
+    device.name().unwrap_or_else(|_| String::from("unknown"))
 }
 
-pub fn iter_audio_devices() {
-    let input_devices: Vec<cpal::Device>;
-    let output_devices: Vec<cpal::Device>;
-    {
-        // dont print any alsa or jack errors on *nix systems to stderr
-        let _print_gag = Gag::stderr().unwrap();
+// builds and starts the stream, errors of the running stream get sent to `stream_events`
+fn open_stream(
+    device: &cpal::Device,
+    kind: DeviceKind,
+    consumers: Consumers,
+    stream_events: mpsc::Sender<StreamEvent>,
+    generation: u64,
+) -> Result<Running, AudioError> {
+    let _print_gag = Gag::stderr().ok();
+
+    let device_config = match kind {
+        DeviceKind::Input => device.default_input_config(),
+        DeviceKind::Output => device.default_output_config(),
+    }
+    .map_err(AudioError::DefaultConfig)?;
+
+    let beat_detector = if consumers.beat_config.enabled {
+        Some(BeatDetector::new(device_config.sample_rate().0, device_config.channels(), consumers.beat_config))
+    } else {
+        None
+    };
+    let pitch_detector = PitchDetector::new(device_config.sample_rate().0, device_config.channels(), consumers.tuner);
+    let meter = Meter::new(device_config.sample_rate().0, device_config.channels(), consumers.meter_config);
+
+    consumers.format.set(device_config.sample_rate().0, device_config.channels());
+    let capacity = RING_SECONDS * device_config.sample_rate().0 as usize * device_config.channels() as usize;
+    let (mut producer, consumer) = ring::channel(capacity, consumers.ring_stats.clone());
 
-        let host = cpal::default_host();
-        input_devices = 
-            host.input_devices()
-            .unwrap()
-            .collect::<Vec<cpal::Device>>();
+    // the callback runs on the real-time thread of the device, so it must not allocate or block
+    let stream = match device_config.sample_format() {
+        cpal::SampleFormat::F32 => device.build_input_stream(
+            &device_config.into(),
+            move |data: &[f32], _: &_| {
+                producer.push(data);
+            },
+            move |err| {
+                stream_events.send(StreamEvent::Error(generation, err)).ok();
+            },
+        ).map_err(AudioError::BuildStream)?,
+        other => {
+            return Err(AudioError::UnsupportedSampleFormat(other));
+        }
+    };
+
+    // stops once the stream and with it the producer get dropped
+    let analysis = thread::spawn(move || run_analysis(consumer, consumers, beat_detector, pitch_detector, meter));
+
+    stream.play().map_err(AudioError::PlayStream)?;
+    Ok(Running { stream, analysis })
+}
+
+// the device after the one of `kind` with `name`, in the order of `list_audio_devices`,
+// None if that would be the same device
+fn next_device(devices: &[(AudioDevice, String)], kind: DeviceKind, name: &str) -> Option<AudioDevice> {
+    let current = devices.iter().position(|(device, device_name)| device_kind(*device) == kind && device_name == name);
+    let next = match current {
+        Some(i) => (i + 1) % devices.len(),
+        None => 0,
+    };
+    match devices.get(next) {
+        Some(_) if Some(next) == current => None,
+        Some((device, _)) => Some(*device),
+        None => None,
+    }
+}
 
-        output_devices = 
-            host.output_devices()
-            .unwrap()
-            .collect::<Vec<cpal::Device>>();
+fn device_kind(device: AudioDevice) -> DeviceKind {
+    match device {
+        AudioDevice::Input(_) | AudioDevice::Monitor => DeviceKind::Input,
+        AudioDevice::Output(_) => DeviceKind::Output,
     }
-    
+}
+
+/// all devices in the order of --iter-devices with their names, the input devices first
+pub fn list_audio_devices() -> Result<Vec<(AudioDevice, String)>, AudioError> {
+    // dont print any alsa or jack errors on *nix systems to stderr
+    let _print_gag = Gag::stderr().ok();
+
+    let host = cpal::default_host();
+    let input_devices = host.input_devices().map_err(AudioError::Devices)?;
+    let output_devices = host.output_devices().map_err(AudioError::Devices)?;
+
+    let inputs = input_devices.enumerate().map(|(i, x)| (AudioDevice::Input(i), device_name(&x)));
+    let outputs = output_devices.enumerate().map(|(i, x)| (AudioDevice::Output(i), device_name(&x)));
+    Ok(inputs.chain(outputs).collect())
+}
+
+pub fn iter_audio_devices() -> Result<(), AudioError> {
+    let devices = list_audio_devices()?;
+
     println!("{}", "[input devices]".cyan().bold());
-    for (i, x) in input_devices.iter().enumerate() {
-        println!("{}: {}", i.to_string().green().bold(), x.name().unwrap());
+    for (device, name) in devices.iter() {
+        if let AudioDevice::Input(i) = device {
+            println!("{}: {}", i.to_string().green().bold(), name);
+        }
     }
-    println!("");
+    println!();
 
     println!("{}", "[output devices]".cyan().bold());
-    for (i, x) in output_devices.iter().enumerate() {
-        println!("{}: {}", i.to_string().green().bold(), x.name().unwrap());
+    for (device, name) in devices.iter() {
+        if let AudioDevice::Output(i) = device {
+            println!("{}: {}", i.to_string().green().bold(), name);
+        }
+    }
+    println!();
+
+    Ok(())
+}
+
+// passes the samples of the ring buffer on to everything that analyses them
+fn run_analysis(
+    mut consumer: ring::Consumer,
+    consumers: Consumers,
+    mut beat_detector: Option<BeatDetector>,
+    mut pitch_detector: PitchDetector,
+    mut meter: Meter,
+) {
+    let mut data = Vec::new();
+    let mut reported_overruns = consumers.ring_stats.overruns();
+    let mut last_report: Option<Instant> = None;
+    loop {
+        let overruns = consumers.ring_stats.overruns();
+        if overruns > reported_overruns && last_report.is_none_or(|time| time.elapsed() >= OVERRUN_REPORT_INTERVAL) {
+            eprintln!(
+                "{} {} samples dropped in {} overruns so far, the analysis falls behind the audio device",
+                "warning:".yellow().bold(),
+                consumers.ring_stats.dropped(),
+                overruns,
+            );
+            reported_overruns = overruns;
+            last_report = Some(Instant::now());
+        }
+
+        // checked before reading, so that the last samples still get passed on
+        let abandoned = consumer.abandoned();
+        if consumer.pop_into(&mut data) > 0 {
+            handle_input_data_f32(
+                std::mem::take(&mut data),
+                &consumers,
+                &mut beat_detector,
+                &mut pitch_detector,
+                &mut meter,
+            );
+        }
+        if abandoned {
+            return;
+        }
+        thread::sleep(ANALYSIS_INTERVAL);
     }
-    println!("");
 }
 
-fn handle_input_data_f32(data: &[f32], sender: mpsc::Sender<audioviz::Event>) {
+fn handle_input_data_f32(
+    data: Vec<f32>,
+    consumers: &Consumers,
+    beat_detector: &mut Option<BeatDetector>,
+    pitch_detector: &mut PitchDetector,
+    meter: &mut Meter,
+) {
+    // meters have to fall during silence as well
+    if !consumers.meters.active() {
+        meter.reset();
+    } else if meter.process(&data) {
+        consumers.meters.publish(Some(meter.levels().clone()));
+    }
+    // skips processing when there is silence
+    if !consumers.activity.process(&data) {
+        consumers.pitch.publish(None);
+        return;
+    }
+    if let Some(beat) = beat_detector.as_mut().and_then(|detector| detector.process(&data)) {
+        consumers.beat_events.publish(beat);
+    }
+    if consumers.pitch.active() && pitch_detector.process(&data) {
+        consumers.pitch.publish(pitch_detector.pitch());
+    }
     // sends the raw data to audio_stream via the event_sender
-    sender.send(audioviz::Event::SendData(data.to_vec())).unwrap();
+    // fails only if audioviz stopped, in which case there is nobody to send to
+    consumers.event_sender.send(audioviz::Event::SendData(data)).ok();
 }
 
-fn err_fn(err: cpal::StreamError) {
-    eprintln!("an error occurred on stream: {}", err);
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn device_indices() {
+        assert!(check_index(DeviceKind::Input, 0, 1).is_ok());
+        assert!(check_index(DeviceKind::Output, 2, 3).is_ok());
+
+        let error = check_index(DeviceKind::Input, 3, 3).unwrap_err();
+        assert!(matches!(error, AudioError::NoSuchDevice("input", 3, 3)));
+        assert_eq!(error.to_string(), "there is no input device 3, available are 0 to 2, see --iter-devices");
+    }
+
+    #[test]
+    fn next_device_by_name() {
+        let devices = vec![
+            (AudioDevice::Input(0), String::from("mic")),
+            (AudioDevice::Input(1), String::from("line")),
+            (AudioDevice::Output(0), String::from("speakers")),
+        ];
+        assert_eq!(next_device(&devices, DeviceKind::Input, "mic"), Some(AudioDevice::Input(1)));
+        assert_eq!(next_device(&devices, DeviceKind::Output, "speakers"), Some(AudioDevice::Input(0)));
+        // the index of a device changes when others get plugged in, its name stays
+        assert_eq!(next_device(&devices[1..], DeviceKind::Input, "line"), Some(AudioDevice::Output(0)));
+        // an input and an output device can have the same name
+        assert_eq!(next_device(&devices, DeviceKind::Output, "mic"), Some(AudioDevice::Input(0)));
+
+        assert_eq!(next_device(&devices[..1], DeviceKind::Input, "mic"), None);
+        assert_eq!(next_device(&[], DeviceKind::Input, "mic"), None);
+    }
+
+    #[test]
+    fn no_devices() {
+        let error = check_index(DeviceKind::Output, 0, 0).unwrap_err();
+        assert!(matches!(error, AudioError::NoSuchDevice("output", 0, 0)));
+        assert_eq!(error.to_string(), "there is no output device 0, no output devices are available");
+    }
+}
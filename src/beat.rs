@@ -0,0 +1,282 @@
+use std::sync::{mpsc, Mutex};
+use crate::config;
+
+// length of the windows whose energies get compared
+const WINDOW_DURATION: f32 = 0.02;
+// duration of the energy history the current window is compared to
+const HISTORY_DURATION: f32 = 1.0;
+// amount of intervals between beats used to estimate the bpm
+const INTERVAL_COUNT: usize = 16;
+// longer intervals between beats are pauses and do not count towards the bpm
+const MAX_INTERVAL: f32 = 2.0;
+// windows below this energy never count as beat, so that noise does not trigger beats
+const MIN_ENERGY: f32 = 1.0e-6;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Beat {
+    // ratio of the energy of the beat to the average energy, always above the sensitivity
+    pub strength: f32,
+    pub bpm: Option<f32>,
+}
+
+/// energy based beat detector that can be fed with any stream of f32 samples
+pub struct BeatDetector {
+    sensitivity: f32,
+    min_interval: f32,
+    // samples per second over all channels
+    rate: f32,
+    window_size: usize,
+
+    window_energy: f32,
+    window_position: usize,
+
+    history: Vec<f32>,
+    history_position: usize,
+    history_filled: bool,
+
+    // samples since the start, used as clock
+    samples: u64,
+    last_beat: Option<u64>,
+    intervals: [f32; INTERVAL_COUNT],
+    interval_count: usize,
+}
+
+impl BeatDetector {
+    pub fn new(sample_rate: u32, channels: u16, config: config::Beat) -> Self {
+        let rate = sample_rate as f32 * channels.max(1) as f32;
+        let window_size = ((rate * WINDOW_DURATION) as usize).max(1);
+        let history_size = ((HISTORY_DURATION / WINDOW_DURATION) as usize).max(1);
+
+        Self {
+            sensitivity: config.sensitivity,
+            min_interval: config.min_interval,
+            rate,
+            window_size,
+            window_energy: 0.0,
+            window_position: 0,
+            history: vec![0.0; history_size],
+            history_position: 0,
+            history_filled: false,
+            samples: 0,
+            last_beat: None,
+            intervals: [0.0; INTERVAL_COUNT],
+            interval_count: 0,
+        }
+    }
+
+    /// processes interleaved samples and returns the last beat found in them,
+    /// does not allocate so it is safe to call from the audio callback
+    pub fn process(&mut self, samples: &[f32]) -> Option<Beat> {
+        let mut beat = None;
+
+        for sample in samples {
+            self.window_energy += sample * sample;
+            self.window_position += 1;
+            self.samples += 1;
+
+            if self.window_position >= self.window_size {
+                let energy = self.window_energy / self.window_size as f32;
+                if let Some(b) = self.process_window(energy) {
+                    beat = Some(b);
+                }
+                self.window_energy = 0.0;
+                self.window_position = 0;
+            }
+        }
+
+        beat
+    }
+
+    /// estimated beats per minute, once enough beats were detected
+    pub fn bpm(&self) -> Option<f32> {
+        if self.interval_count < 4 {
+            return None;
+        }
+        let count = self.interval_count.min(INTERVAL_COUNT);
+        let mut intervals = self.intervals;
+        let intervals = &mut intervals[0..count];
+        intervals.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        let median = intervals[count / 2];
+        if median <= 0.0 {
+            return None;
+        }
+
+        // folds the tempo into the common range of music
+        let mut bpm = 60.0 / median;
+        while bpm < 70.0 {
+            bpm *= 2.0;
+        }
+        while bpm > 180.0 {
+            bpm /= 2.0;
+        }
+        Some(bpm)
+    }
+
+    fn process_window(&mut self, energy: f32) -> Option<Beat> {
+        let history_len = if self.history_filled { self.history.len() } else { self.history_position };
+        let average = if history_len > 0 {
+            self.history[0..history_len].iter().sum::<f32>() / history_len as f32
+        } else {
+            0.0
+        };
+
+        self.history[self.history_position] = energy;
+        self.history_position += 1;
+        if self.history_position >= self.history.len() {
+            self.history_position = 0;
+            self.history_filled = true;
+        }
+
+        // waits for a full history, otherwise the start of every song would be a beat
+        if !self.history_filled || energy < MIN_ENERGY || energy <= average * self.sensitivity {
+            return None;
+        }
+
+        let min_interval = (self.min_interval * self.rate) as u64;
+        if let Some(last_beat) = self.last_beat {
+            let interval = self.samples - last_beat;
+            if interval < min_interval {
+                return None;
+            }
+            let interval = interval as f32 / self.rate;
+            if interval <= MAX_INTERVAL {
+                self.intervals[self.interval_count % INTERVAL_COUNT] = interval;
+                self.interval_count += 1;
+            }
+        }
+        self.last_beat = Some(self.samples);
+
+        Some(Beat {
+            strength: if average > 0.0 { energy / average } else { self.sensitivity },
+            bpm: self.bpm(),
+        })
+    }
+}
+
+/// distributes detected beats to every subscriber
+#[derive(Default)]
+pub struct BeatEvents {
+    subscribers: Mutex<Vec<mpsc::Sender<Beat>>>,
+}
+
+impl BeatEvents {
+    pub fn new() -> Self {
+        Self {
+            subscribers: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn subscribe(&self) -> mpsc::Receiver<Beat> {
+        let (tx, rx) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+
+    pub fn publish(&self, beat: Beat) {
+        // subscribers that dropped their receiver get removed
+        self.subscribers.lock().unwrap().retain(|subscriber| subscriber.send(beat).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48000;
+    const CHANNELS: u16 = 2;
+    const DURATION: f32 = 10.0;
+
+    fn detector(min_interval: f32) -> BeatDetector {
+        let config = config::Beat {
+            min_interval,
+            ..crate::Config::default().beat
+        };
+        BeatDetector::new(SAMPLE_RATE, CHANNELS, config)
+    }
+
+    // stereo signal of the given length, `sample` gets the time in seconds
+    fn signal(mut sample: impl FnMut(f32) -> f32) -> Vec<f32> {
+        let frames = (DURATION * SAMPLE_RATE as f32) as usize;
+        (0..frames)
+            .flat_map(|frame| {
+                let value = sample(frame as f32 / SAMPLE_RATE as f32);
+                [value, value]
+            })
+            .collect()
+    }
+
+    // 10 ms clicks every `interval` seconds on top of silence
+    fn clicks(interval: f32, amplitude: f32) -> Vec<f32> {
+        signal(|time| if time % interval < 0.01 { amplitude } else { 0.0 })
+    }
+
+    // uniform noise from a fixed seed
+    fn noise(amplitude: f32) -> Vec<f32> {
+        let mut state: u32 = 0x1234_5678;
+        signal(move |_| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state as f32 / u32::MAX as f32 * 2.0 - 1.0) * amplitude
+        })
+    }
+
+    // feeds the signal in chunks like the audio callback and returns the beats with their time
+    fn beats(detector: &mut BeatDetector, samples: &[f32]) -> Vec<(f32, Beat)> {
+        let chunk = 512 * CHANNELS as usize;
+        samples
+            .chunks(chunk)
+            .enumerate()
+            .filter_map(|(i, chunk)| {
+                let time = (i * 512) as f32 / SAMPLE_RATE as f32;
+                detector.process(chunk).map(|beat| (time, beat))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn click_track_at_120_bpm() {
+        let mut detector = detector(0.25);
+        let beats = beats(&mut detector, &clicks(0.5, 0.5));
+
+        // the first second only fills the history
+        assert_eq!(beats.len(), 18);
+        assert!(beats.iter().all(|(_, beat)| beat.strength > 1.4));
+        let bpm = beats.last().unwrap().1.bpm.unwrap();
+        assert!((bpm - 120.0).abs() < 1.0, "{}", bpm);
+        assert_eq!(detector.bpm(), Some(bpm));
+    }
+
+    #[test]
+    fn beats_respect_min_interval() {
+        let mut detector = detector(0.25);
+        let beats = beats(&mut detector, &clicks(0.1, 0.5));
+
+        assert!(beats.len() > 10);
+        for pair in beats.windows(2) {
+            // chunks are about 10 ms long
+            assert!(pair[1].0 - pair[0].0 >= 0.25 - 0.011, "{:?}", pair);
+        }
+    }
+
+    #[test]
+    fn silence_has_no_beats() {
+        let mut detector = detector(0.25);
+        assert!(beats(&mut detector, &signal(|_| 0.0)).is_empty());
+        assert_eq!(detector.bpm(), None);
+    }
+
+    #[test]
+    fn steady_noise_has_no_beats() {
+        let mut detector = detector(0.25);
+        assert!(beats(&mut detector, &noise(0.5)).is_empty());
+    }
+
+    #[test]
+    fn quiet_clicks_are_below_min_energy() {
+        // the clicks exceed the average by far but are below MIN_ENERGY
+        let mut quiet = detector(0.25);
+        assert!(beats(&mut quiet, &clicks(0.5, 1.0e-3)).is_empty());
+
+        let mut louder = detector(0.25);
+        assert!(!beats(&mut louder, &clicks(0.5, 1.0e-2)).is_empty());
+    }
+}
@@ -1,5 +1,439 @@
+use serde_derive::{Deserialize, Serialize};
+use winit::event::VirtualKeyCode;
+use crate::amplitude::{self, BinScale};
+use crate::graphics::theme::{self, Theme};
+
+// documented default configuration, also used as base for every user configuration
+pub const DEFAULT_CONFIG: &str = include_str!("default_config.toml");
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Visualisation {
     Bars,
     Strings,
     Circle,
-}
\ No newline at end of file
+    PianoRoll,
+    Tuner,
+}
+
+impl Visualisation {
+    const ALL: [Visualisation; 5] = [
+        Visualisation::Bars,
+        Visualisation::Strings,
+        Visualisation::Circle,
+        Visualisation::PianoRoll,
+        Visualisation::Tuner,
+    ];
+
+    pub fn next(self) -> Self {
+        let i = Self::ALL.iter().position(|v| *v == self).unwrap_or(0);
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+
+    pub fn previous(self) -> Self {
+        let i = Self::ALL.iter().position(|v| *v == self).unwrap_or(0);
+        Self::ALL[(i + Self::ALL.len() - 1) % Self::ALL.len()]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Layout {
+    Bottom,
+    Top,
+    Center,
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Growth {
+    Outward,
+    Inward,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    VolumeUp,
+    VolumeDown,
+    SmoothingUp,
+    SmoothingDown,
+    NextVisualisation,
+    PreviousVisualisation,
+    NextTheme,
+    Freeze,
+    ToggleOverlay,
+    ToggleAutoGain,
+    ToggleMeters,
+    Screenshot,
+    ToggleRecording,
+    NextDevice,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RecordingFormat {
+    Gif,
+    Png,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PresentMode {
+    Fifo,
+    Mailbox,
+    Immediate,
+}
+
+impl PresentMode {
+    pub fn to_wgpu(self) -> wgpu::PresentMode {
+        match self {
+            PresentMode::Fifo => wgpu::PresentMode::Fifo,
+            PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+            PresentMode::Immediate => wgpu::PresentMode::Immediate,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AmplitudeMode {
+    Power,
+    Decibel,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Weighting {
+    None,
+    A,
+    C,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Config {
+    pub fps: u32,
+    pub present_mode: PresentMode,
+    pub visualisation: Visualisation,
+    pub width: f32,
+    pub theme: String,
+    pub themes: Vec<Theme>,
+    pub bars: Bars,
+    pub strings: Strings,
+    pub circle: Circle,
+    pub amplitude: Amplitude,
+    pub agc: Agc,
+    pub idle: Idle,
+    pub beat: Beat,
+    pub osc: Osc,
+    pub capture: Capture,
+    pub keys: Keys,
+    pub reconnect: Reconnect,
+    pub tuner: Tuner,
+    pub meters: Meters,
+    pub analysis: Analysis,
+    pub audio: Audio,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Bars {
+    pub layout: Layout,
+    pub mirror: bool,
+    pub gap: f32,
+    pub min_height: f32,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Strings {
+    pub mirror: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Circle {
+    pub radius: f32,
+    pub start_angle: f32,
+    pub span: f32,
+    pub mirror: bool,
+    pub growth: Growth,
+    pub filled: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Amplitude {
+    pub mode: AmplitudeMode,
+    pub volume_amplitude: f32,
+    pub volume_factoring: f32,
+    pub db_floor: f32,
+    pub db_ceiling: f32,
+    pub db_offset: f32,
+    pub weighting: Weighting,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Agc {
+    pub enabled: bool,
+    pub target: f32,
+    pub attack: f32,
+    pub release: f32,
+    pub min_gain: f32,
+    pub max_gain: f32,
+    pub silence_threshold: f32,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Idle {
+    pub enabled: bool,
+    pub threshold: f32,
+    pub timeout: f32,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Beat {
+    pub enabled: bool,
+    pub sensitivity: f32,
+    pub min_interval: f32,
+    pub circle_pulse: f32,
+    pub flash: f32,
+    pub decay: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Osc {
+    pub enabled: bool,
+    pub target: String,
+    pub prefix: String,
+    pub bands: usize,
+    pub rate: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Capture {
+    pub directory: String,
+    pub recording_format: RecordingFormat,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Keys {
+    pub volume_up: VirtualKeyCode,
+    pub volume_down: VirtualKeyCode,
+    pub smoothing_up: VirtualKeyCode,
+    pub smoothing_down: VirtualKeyCode,
+    pub next_visualisation: VirtualKeyCode,
+    pub previous_visualisation: VirtualKeyCode,
+    pub next_theme: VirtualKeyCode,
+    pub freeze: VirtualKeyCode,
+    pub toggle_overlay: VirtualKeyCode,
+    pub toggle_auto_gain: VirtualKeyCode,
+    pub toggle_meters: VirtualKeyCode,
+    pub screenshot: VirtualKeyCode,
+    pub toggle_recording: VirtualKeyCode,
+    pub next_device: VirtualKeyCode,
+}
+
+impl Keys {
+    pub fn action(&self, key: VirtualKeyCode) -> Option<Action> {
+        let keymap = [
+            (self.volume_up, Action::VolumeUp),
+            (self.volume_down, Action::VolumeDown),
+            (self.smoothing_up, Action::SmoothingUp),
+            (self.smoothing_down, Action::SmoothingDown),
+            (self.next_visualisation, Action::NextVisualisation),
+            (self.previous_visualisation, Action::PreviousVisualisation),
+            (self.next_theme, Action::NextTheme),
+            (self.freeze, Action::Freeze),
+            (self.toggle_overlay, Action::ToggleOverlay),
+            (self.toggle_auto_gain, Action::ToggleAutoGain),
+            (self.toggle_meters, Action::ToggleMeters),
+            (self.screenshot, Action::Screenshot),
+            (self.toggle_recording, Action::ToggleRecording),
+            (self.next_device, Action::NextDevice),
+        ];
+        keymap.iter().find(|(k, _)| *k == key).map(|(_, action)| *action)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Reconnect {
+    pub enabled: bool,
+    pub fallback_to_default: bool,
+    pub max_delay: f32,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Tuner {
+    pub min_frequency: f32,
+    pub max_frequency: f32,
+    pub threshold: f32,
+    pub reference: f32,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Meters {
+    pub enabled: bool,
+    pub hold: f32,
+    pub peak_decay: f32,
+    pub floor: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Engine {
+    Audioviz,
+    Fft,
+    ConstantQ,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum WindowFunction {
+    Hann,
+    BlackmanHarris,
+    FlatTop,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SpectrumScale {
+    Magnitude,
+    Power,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Analysis {
+    pub engine: Engine,
+    pub fft_size: usize,
+    pub overlap: f32,
+    pub window: WindowFunction,
+    pub zero_padding: usize,
+    pub scale: SpectrumScale,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Audio {
+    pub density_reduction: usize,
+    pub smoothing_size: usize,
+    pub smoothing_amount: usize,
+    pub frequency_scale_range: [usize; 2],
+    pub frequency_scale_amount: usize,
+    pub max_frequency: usize,
+    pub buffering: usize,
+    pub resolution: usize,
+    pub refresh_rate: usize,
+    pub volume: f32,
+}
+
+impl Audio {
+    pub fn to_audioviz(self) -> audioviz::Config {
+        audioviz::Config {
+            density_reduction: self.density_reduction,
+            smoothing_size: self.smoothing_size,
+            smoothing_amount: self.smoothing_amount,
+            frequency_scale_range: self.frequency_scale_range,
+            frequency_scale_amount: self.frequency_scale_amount,
+            max_frequency: self.max_frequency,
+            buffering: self.buffering,
+            resolution: self.resolution,
+            refresh_rate: self.refresh_rate,
+            volume: self.volume,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config::parse(DEFAULT_CONFIG).expect("default configuration is invalid")
+    }
+}
+
+impl Config {
+    /// parses and validates a configuration, missing options are taken from the default configuration
+    pub fn parse(config: &str) -> Result<Self, toml::de::Error> {
+        let mut value: toml::Value = toml::from_str(DEFAULT_CONFIG)?;
+        let user: toml::Value = toml::from_str(config)?;
+        merge(&mut value, user);
+
+        let config: Config = value.try_into()?;
+        config.validate().map_err(<toml::de::Error as serde::de::Error>::custom)?;
+        Ok(config)
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let config = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {}", path, e))?;
+
+        Config::parse(&config)
+            .map_err(|e| format!("failed to parse {}: {}", path, e))
+    }
+
+    /// checks the values that would break the analysis or make no sense, already done by `parse`
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.all_themes().iter().any(|theme| theme.name == self.theme) {
+            return Err(format!("unknown theme: {}", self.theme));
+        }
+
+        amplitude::validate(&self.amplitude, self.analysis.engine)?;
+
+        let tuner = &self.tuner;
+        if tuner.min_frequency <= 0.0 || tuner.min_frequency >= tuner.max_frequency {
+            return Err(format!(
+                "tuner frequencies have to be positive with min_frequency below max_frequency, not {} and {}",
+                tuner.min_frequency, tuner.max_frequency
+            ));
+        }
+        if tuner.reference <= 0.0 {
+            return Err(format!("tuner reference has to be positive, not {}", tuner.reference));
+        }
+
+        if self.meters.floor >= 0.0 {
+            return Err(format!("meters floor has to be below 0.0 dBFS, not {}", self.meters.floor));
+        }
+
+        let analysis = &self.analysis;
+        if !analysis.fft_size.is_power_of_two() || !(512..=16384).contains(&analysis.fft_size) {
+            return Err(format!("fft_size has to be a power of two from 512 to 16384, not {}", analysis.fft_size));
+        }
+        if !(0.0..=0.95).contains(&analysis.overlap) {
+            return Err(format!("overlap has to be between 0.0 and 0.95, not {}", analysis.overlap));
+        }
+        if !analysis.zero_padding.is_power_of_two() || analysis.zero_padding > 8 {
+            return Err(format!("zero_padding has to be 1, 2, 4 or 8, not {}", analysis.zero_padding));
+        }
+        Ok(())
+    }
+
+    /// how the bins of the spectrum of the selected engine are spread over the frequencies
+    pub fn bin_scale(&self) -> BinScale {
+        match self.analysis.engine {
+            Engine::ConstantQ => BinScale::Notes,
+            Engine::Audioviz | Engine::Fft => BinScale::Spectrum(self.audio.max_frequency as f32),
+        }
+    }
+
+    /// built in and user defined themes
+    pub fn all_themes(&self) -> Vec<Theme> {
+        theme::all(&self.themes)
+    }
+}
+
+// recursively overwrites the values of `base` with the ones of `other`
+fn merge(base: &mut toml::Value, other: toml::Value) {
+    match (base, other) {
+        (toml::Value::Table(base), toml::Value::Table(other)) => {
+            for (key, value) in other {
+                match base.get_mut(&key) {
+                    Some(base_value) => merge(base_value, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, other) => *base = other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_validates() {
+        assert!(Config::parse("").is_ok());
+        let e = Config::parse("[analysis]\nfft_size = 0").unwrap_err();
+        assert!(e.to_string().contains("fft_size"), "{}", e);
+        assert!(Config::parse("[tuner]\nmin_frequency = 3000.0").is_err());
+        assert!(Config::parse("[amplitude]\nmode = \"Decibel\"").is_err());
+        assert!(Config::parse("[amplitude]\nmode = \"Decibel\"\n[analysis]\nengine = \"Fft\"").is_ok());
+    }
+}
@@ -0,0 +1,258 @@
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use colored::*;
+use crate::config::{self, RecordingFormat};
+use crate::graphics::visualiser::Visualiser;
+
+// frames that can be queued for encoding before frames of the recording get dropped
+const RECORDING_QUEUE: usize = 8;
+// quality of the gif palette, 1 is the best and slowest, 30 the worst and fastest
+const GIF_SPEED: i32 = 10;
+
+/// one frame copied back from the gpu
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    // rgba, 4 bytes per pixel without padding
+    pub pixels: Vec<u8>,
+}
+
+/// renders the visualisation into an offscreen texture and copies it back to the cpu,
+/// `format` has to be the format the visualiser was created with, blocks until the gpu is done
+pub fn read_frame(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    visualiser: &Visualiser,
+    format: wgpu::TextureFormat,
+    size: [u32; 2],
+) -> Result<Frame, String> {
+    let bgra = match format {
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+        wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+        other => return Err(format!("can not capture frames of format {:?}", other)),
+    };
+    let [width, height] = size;
+    let extent = wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Capture Texture"),
+        size: extent,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    visualiser.render(device, queue, &view);
+
+    // rows of the copy have to be aligned to 256 bytes
+    let row_size = width * 4;
+    let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let padded_row_size = row_size.div_ceil(alignment) * alignment;
+
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Capture Buffer"),
+        size: (padded_row_size * height) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Capture Encoder"),
+    });
+    encoder.copy_texture_to_buffer(
+        wgpu::ImageCopyTexture {
+            texture: &texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        wgpu::ImageCopyBuffer {
+            buffer: &buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(padded_row_size),
+                rows_per_image: NonZeroU32::new(height),
+            },
+        },
+        extent,
+    );
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let slice = buffer.slice(..);
+    let mapping = slice.map_async(wgpu::MapMode::Read);
+    device.poll(wgpu::Maintain::Wait);
+    pollster::block_on(mapping).map_err(|e| format!("failed to read frame: {:?}", e))?;
+
+    let mut pixels: Vec<u8> = Vec::with_capacity((row_size * height) as usize);
+    {
+        let data = slice.get_mapped_range();
+        for row in data.chunks_exact(padded_row_size as usize) {
+            pixels.extend_from_slice(&row[0..row_size as usize]);
+        }
+    }
+    buffer.unmap();
+
+    for pixel in pixels.chunks_exact_mut(4) {
+        if bgra {
+            pixel.swap(0, 2);
+        }
+        // the background is cleared with an alpha of 0, but shown opaque
+        pixel[3] = 255;
+    }
+
+    Ok(Frame {
+        width,
+        height,
+        pixels,
+    })
+}
+
+/// saves screenshots and recordings of frames
+pub struct Capture {
+    config: config::Capture,
+    recording: Option<mpsc::SyncSender<(Frame, Instant)>>,
+}
+
+impl Capture {
+    pub fn new(config: config::Capture) -> Self {
+        Self {
+            config,
+            recording: None,
+        }
+    }
+
+    pub fn recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// saves the frame as png and returns its path
+    pub fn screenshot(&self, frame: &Frame) -> Result<PathBuf, String> {
+        let path = Path::new(&self.config.directory).join(format!("{}.png", file_name()));
+        write_png(&path, frame)?;
+        Ok(path)
+    }
+
+    /// starts a recording and returns the path it gets saved to
+    pub fn start_recording(&mut self) -> Result<PathBuf, String> {
+        let format = self.config.recording_format;
+        let path = Path::new(&self.config.directory).join(match format {
+            RecordingFormat::Gif => format!("{}.gif", file_name()),
+            RecordingFormat::Png => file_name(),
+        });
+        if format == RecordingFormat::Png {
+            fs::create_dir_all(&path).map_err(|e| format!("failed to create {}: {}", path.display(), e))?;
+        }
+
+        let (tx, rx) = mpsc::sync_channel(RECORDING_QUEUE);
+        let recording_path = path.clone();
+        // encoding is too slow for the render loop, especially the palettes of gifs
+        thread::spawn(move || {
+            let result = match format {
+                RecordingFormat::Gif => write_gif(&recording_path, rx),
+                RecordingFormat::Png => write_png_sequence(&recording_path, rx),
+            };
+            match result {
+                Ok(frames) => println!(
+                    "saved recording with {} frames: {}",
+                    frames,
+                    recording_path.display().to_string().green().bold(),
+                ),
+                Err(e) => eprintln!("{} {}", "failed to save recording:".red().bold(), e),
+            }
+        });
+        self.recording = Some(tx);
+
+        Ok(path)
+    }
+
+    /// stops the recording, its remaining frames get saved in the background
+    pub fn stop_recording(&mut self) {
+        self.recording = None;
+    }
+
+    /// adds a frame to the recording, frames get dropped if encoding can not keep up
+    pub fn record(&mut self, frame: Frame) {
+        if let Some(recording) = &self.recording {
+            if let Err(mpsc::TrySendError::Disconnected(_)) = recording.try_send((frame, Instant::now())) {
+                // the encoder failed and already reported why
+                self.recording = None;
+            }
+        }
+    }
+}
+
+// unique name for every capture, milliseconds since the unix epoch
+fn file_name() -> String {
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+    format!("audiovis-{}", millis)
+}
+
+fn write_png(path: &Path, frame: &Frame) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("failed to create {}: {}", path.display(), e))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), frame.width, frame.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&frame.pixels))
+        .map_err(|e| format!("failed to write {}: {}", path.display(), e))
+}
+
+fn write_png_sequence(directory: &Path, frames: mpsc::Receiver<(Frame, Instant)>) -> Result<usize, String> {
+    let mut count = 0;
+    for (frame, _) in frames.iter() {
+        write_png(&directory.join(format!("{:05}.png", count)), &frame)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+// the size of the first frame is the size of the gif, frames of other sizes are skipped
+fn write_gif(path: &Path, frames: mpsc::Receiver<(Frame, Instant)>) -> Result<usize, String> {
+    let mut frames = frames.iter();
+    let mut pending = match frames.next() {
+        Some(frame) => frame,
+        None => return Ok(0),
+    };
+    let (width, height) = (pending.0.width, pending.0.height);
+
+    let file = File::create(path).map_err(|e| format!("failed to create {}: {}", path.display(), e))?;
+    let error = |e: gif::EncodingError| format!("failed to write {}: {}", path.display(), e);
+    let mut encoder = gif::Encoder::new(BufWriter::new(file), width as u16, height as u16, &[]).map_err(error)?;
+    encoder.set_repeat(gif::Repeat::Infinite).map_err(error)?;
+
+    let mut write = |frame: &mut Frame, delay: f32| -> Result<(), String> {
+        let mut gif_frame = gif::Frame::from_rgba_speed(width as u16, height as u16, &mut frame.pixels, GIF_SPEED);
+        // in hundredths of a second
+        gif_frame.delay = (delay * 100.0).round().max(1.0) as u16;
+        encoder.write_frame(&gif_frame).map_err(error)
+    };
+
+    // the delay of a frame is only known once the next one arrives
+    let mut count = 0;
+    let mut delay = 0.0;
+    for (frame, time) in frames {
+        if frame.width != width || frame.height != height {
+            continue;
+        }
+        delay = (time - pending.1).as_secs_f32();
+        write(&mut pending.0, delay)?;
+        count += 1;
+        pending = (frame, time);
+    }
+    // the last frame is shown as long as the one before it
+    write(&mut pending.0, delay)?;
+
+    Ok(count + 1)
+}
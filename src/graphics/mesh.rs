@@ -1,59 +1,111 @@
-use crate::graphics::wgpu_abstraction::Vertex;
-use crate::config::Visualisation;
-use std::f32::consts::PI;
+use crate::graphics::theme::{Direction, Theme};
+use crate::config::{self, Config, Growth, Layout, Visualisation};
+use crate::meter::Levels;
+use crate::notes;
 
+/// height of the keyboard of PianoRoll in clip space, at the bottom of the window
+pub const KEYBOARD_HEIGHT: f32 = 0.15;
+// colors of the keys of PianoRoll while they are silent
+const WHITE_KEY: [f32; 3] = [0.85, 0.85, 0.85];
+const BLACK_KEY: [f32; 3] = [0.15, 0.15, 0.15];
+// space between two keys, relative to their width
+const KEY_GAP: f32 = 0.1;
+
+/// pivot of the needle of Tuner in clip space, its scale is above and its text below
+pub const TUNER_PIVOT: [f32; 2] = [0.0, -0.1];
+// length of the needle, relative to the shorter side of the window
+const TUNER_RADIUS: f32 = 0.8;
+// angle of the needle at 50 cents off in degrees
+const TUNER_ANGLE: f32 = 60.0;
+
+/// top and bottom of the meters in clip space
+pub const METER_TOP: f32 = 0.8;
+pub const METER_BOTTOM: f32 = -0.8;
+// width of a meter, the space between two of them and to the edge of the window, in pixels of the text
+const METER_WIDTH: f32 = 6.0;
+const METER_GAP: f32 = 2.0;
+const METER_MARGIN: f32 = 4.0;
+const METER_TRACK: [f32; 3] = [0.15, 0.15, 0.15];
+const METER_MARK: [f32; 3] = [1.0, 1.0, 1.0];
+const METER_CLIP: [f32; 3] = [1.0, 0.2, 0.2];
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub color: [f32; 3],
+}
+
+/// generates the mesh of the visualisation, except for Tuner which is drawn by `tuner`,
+/// `buffer` has to be already scaled into heights via `amplitude::scale_buffer`,
+/// `time` in seconds is used for animated themes,
+/// `pulse` is the strength of the reaction to the last beat (0.0 - 1.0)
 pub fn from_buffer(
-    buffer: Vec<f32>,
-    visualisation: Visualisation,
-    width: f32,
-    volume_amplitude: f32,
-    volume_factoring: f32,
-    top_color: [f32; 3],
-    bottom_color: [f32; 3],
+    buffer: &[f32],
+    config: &Config,
+    theme: &Theme,
+    time: f32,
+    pulse: f32,
     size: [f32; 2],
 ) -> (Vec<Vertex>, Vec<u32>)  {
 
     let mut vertices: Vec<Vertex> = Vec::new();
     let mut indices: Vec<u32> = Vec::new();
-    let buffer_len = buffer.len();
 
-    if buffer.len() == 0 {
+    if buffer.is_empty() {
         return (Vec::new(), Vec::new());
     }
 
-    match visualisation {
+    match config.visualisation {
         Visualisation::Bars => {
-            let width: f32 = 1.0 / buffer_len as f32 * width;
-            for i in 0..buffer.len() {
-                let x = (i as f32 - buffer_len as f32 / 2.0) / (buffer_len as f32 / 2.0) + width;
-                let y: f32 = volume_amplitude * ( (buffer[i] as f32).powf(volume_factoring) ) - 1.0;
-
-                let top_color: [f32; 3] = [ top_color[0] * (y + 1.0),  top_color[1] * (y + 1.0),  top_color[2] * (y + 1.0)];
-
-                vertices.push(Vertex { position: [x - width,  -1.0, 0.0],   color:  bottom_color });
-                vertices.push(Vertex { position: [x + width,  -1.0, 0.0],   color:  bottom_color });
-
-                vertices.push(Vertex { position: [x - width,  y, 0.0],   color: top_color });
-                vertices.push(Vertex { position: [x + width,  y, 0.0],   color: top_color });
-
-                let i = vertices.len() as u32 - 4;
-                indices.push(i+0);
-                indices.push(i+3);
-                indices.push(i+2);
-                indices.push(i+0);
-                indices.push(i+1);
-                indices.push(i+3);
+            let bars = &config.bars;
+            let buffer: Vec<f32> = if bars.mirror { mirror(buffer) } else { buffer.to_vec() };
+            let buffer_len = buffer.len();
+            // space every bar has along the spectrum and the part of it that gets filled
+            let slot: f32 = 2.0 / buffer_len as f32;
+            let width: f32 = slot * config.width * (1.0 - bars.gap.clamp(0.0, 1.0));
+
+            for (i, value) in buffer.iter().enumerate() {
+                let center = -1.0 + slot * (i as f32 + 0.5);
+                let (a1, a2) = (center - width / 2.0, center + width / 2.0);
+                let frequency = i as f32 / buffer_len as f32;
+                // portion of the window height
+                let height = (value.max(0.0) / 2.0).max(bars.min_height);
+
+                // splits the bar at every color stop so that every stop of the gradient is visible
+                let mut start: f32 = 0.0;
+                for end in theme.stops_between(0.0, height).chain(std::iter::once(height)) {
+                    let start_color = gradient_color(theme, start, frequency, time);
+                    let end_color = gradient_color(theme, end, frequency, time);
+
+                    for (g1, g2) in bar_extents(bars.layout, start, end) {
+                        let corners = match bars.layout {
+                            Layout::Left | Layout::Right => [[g1, a1], [g1, a2], [g2, a2], [g2, a1]],
+                            _ => [[a1, g1], [a2, g1], [a2, g2], [a1, g2]],
+                        };
+                        push_quad(
+                            &mut vertices,
+                            &mut indices,
+                            corners,
+                            [start_color, start_color, end_color, end_color],
+                        );
+                    }
+
+                    start = end;
+                }
             }
         },
         Visualisation::Strings => {
-            let width = width * 0.005;
+            let buffer: &[f32] = &if config.strings.mirror { mirror(buffer) } else { buffer.to_vec() };
+            let buffer_len = buffer.len();
+            let width = config.width * 0.005;
             for i in 0..buffer.len() - 1 {
                 let x1: f32 = (i as f32 - buffer_len as f32 / 2.0) / (buffer_len as f32 / 2.0);
                 let x2: f32 = ((i + 1) as f32 - buffer_len as f32 / 2.0) / (buffer_len as f32 / 2.0);
-                let y1: f32 = volume_amplitude * ( (buffer[i] as f32).powf(volume_factoring)) - 1.0;
-                let y2: f32 = volume_amplitude * ( (buffer[i + 1] as f32).powf(volume_factoring)) - 1.0;
+                let y1: f32 = buffer[i] - 1.0;
+                let y2: f32 = buffer[i + 1] - 1.0;
 
-                let color: [f32; 3] = [top_color[0] * (y1 + 1.0), top_color[1] * (y1 + 1.0), top_color[2] * (y1 + 1.0)];
+                let color = gradient_color(theme, buffer[i] / 2.0, i as f32 / buffer_len as f32, time);
 
                 let (mut vertices2, mut indices2) = draw_line(
                     [x1, y1], 
@@ -67,45 +119,284 @@ pub fn from_buffer(
             }
         },
         Visualisation::Circle => {
-            let width = width * 0.005;
-            let radius: f32 = 0.3;
-            let mut last_x: f32 = 0.0;
-            let mut last_y: f32 = 0.0;
-
-            for i in 0..buffer.len() - 1 {
-                let mut angle: f32 = 2.0 * PI * (i + 1) as f32 / (buffer.len() - 2) as f32;
-                let degree: f32 = 2.0 * PI / 360.0;
-                angle += degree * 270.0; // rotate circle 270°
-
-                let value: f32 = buffer[i];
+            let width = config.width * 0.005;
+            let circle = &config.circle;
+            let radius = circle.radius + pulse * config.beat.circle_pulse;
+            let buffer: Vec<f32> = if circle.mirror { mirror(buffer) } else { buffer.to_vec() };
+            let buffer_len = buffer.len();
 
-                let x: f32 = angle.cos() * (value + radius) / size[0];
-                let y: f32 = angle.sin() * (value + radius) / size[1];
+            // a full circle gets closed, so the last point must not overlap the first one
+            let full_circle = circle.span.abs() >= 360.0;
+            let segments = if full_circle { buffer_len } else { buffer_len.saturating_sub(1).max(1) };
 
-                let r: f32 = (top_color[0] * value) + (bottom_color[0] * (1.0 / value));
-                let g: f32 = (top_color[1] * value) + (bottom_color[1] * (1.0 / value));
-                let b: f32 = (top_color[2] * value) + (bottom_color[2] * (1.0 / value));
+            let point = |i: usize, radius: f32| -> [f32; 2] {
+                let angle = (circle.start_angle + circle.span * i as f32 / segments as f32).to_radians();
+                [angle.cos() * radius / size[0], angle.sin() * radius / size[1]]
+            };
+            let spectrum_radius = |value: f32| -> f32 {
+                match circle.growth {
+                    Growth::Outward => radius + value,
+                    Growth::Inward => (radius - value).max(0.0),
+                }
+            };
 
-                let color: [f32; 3] = [r, g ,b];
+            let lines = if full_circle { buffer_len } else { buffer_len - 1 };
+            for i in 0..lines {
+                let j = (i + 1) % buffer_len;
+                let frequency = i as f32 / buffer_len as f32;
 
-                if i != 0 {
+                if circle.filled {
+                    let base_color = gradient_color(theme, 0.0, frequency, time);
+                    push_quad(
+                        &mut vertices,
+                        &mut indices,
+                        [
+                            point(i, radius),
+                            point(i + 1, radius),
+                            point(i + 1, spectrum_radius(buffer[j])),
+                            point(i, spectrum_radius(buffer[i])),
+                        ],
+                        [
+                            base_color,
+                            base_color,
+                            gradient_color(theme, buffer[j] / 2.0, frequency, time),
+                            gradient_color(theme, buffer[i] / 2.0, frequency, time),
+                        ],
+                    );
+                } else {
                     let (mut vertices2, mut indices2) = draw_line(
-                        [last_x, last_y], 
-                        [x, y], 
-                        width, 
-                        color, 
-                        vertices.len() as u32, 
+                        point(i, spectrum_radius(buffer[i])),
+                        point(i + 1, spectrum_radius(buffer[j])),
+                        width,
+                        gradient_color(theme, buffer[i] / 2.0, frequency, time),
+                        vertices.len() as u32,
                         size
                     );
                     vertices.append(&mut vertices2);
                     indices.append(&mut indices2);
                 }
-                last_x = x;
-                last_y = y;
             }
         },
+        Visualisation::PianoRoll => {
+            // one key of the piano per value, lit in the color of its level, with its level above it
+            let buffer_len = buffer.len();
+            let slot = 2.0 / buffer_len as f32;
+            let keyboard_top = -1.0 + KEYBOARD_HEIGHT;
+
+            for (i, value) in buffer.iter().enumerate() {
+                let x1 = -1.0 + slot * i as f32;
+                let x2 = x1 + slot * (1.0 - KEY_GAP);
+                let frequency = i as f32 / buffer_len as f32;
+                let height = (value.max(0.0) / 2.0).min(1.0);
+                let color = gradient_color(theme, height, frequency, time);
+
+                let note = notes::LOWEST.saturating_add(i as u8);
+                let key = if notes::is_black(note) { BLACK_KEY } else { WHITE_KEY };
+                let lit = [0, 1, 2].map(|c| key[c] + (color[c] - key[c]) * height);
+                push_quad(
+                    &mut vertices,
+                    &mut indices,
+                    [[x1, -1.0], [x2, -1.0], [x2, keyboard_top], [x1, keyboard_top]],
+                    [lit; 4],
+                );
+
+                let bottom = keyboard_top + slot * KEY_GAP;
+                let top = bottom + (1.0 - bottom) * height;
+                if top > bottom {
+                    let bottom_color = gradient_color(theme, 0.0, frequency, time);
+                    push_quad(
+                        &mut vertices,
+                        &mut indices,
+                        [[x1, bottom], [x2, bottom], [x2, top], [x1, top]],
+                        [bottom_color, bottom_color, color, color],
+                    );
+                }
+            }
+        },
+        // does not show the spectrum
+        Visualisation::Tuner => {},
+    }
+    (vertices, indices)
+}
+
+/// generates the mesh of Tuner, a scale from -50 to +50 cents and a needle at `cents`,
+/// without a pitch there is no needle, `size` as for `from_buffer`
+pub fn tuner(cents: Option<f32>, config: &Config, theme: &Theme, time: f32, size: [f32; 2]) -> (Vec<Vertex>, Vec<u32>) {
+    let mut vertices: Vec<Vertex> = Vec::new();
+    let mut indices: Vec<u32> = Vec::new();
+
+    let point = |cents: f32, radius: f32| -> [f32; 2] {
+        let angle = (90.0 - cents / 50.0 * TUNER_ANGLE).to_radians();
+        [
+            TUNER_PIVOT[0] + angle.cos() * radius * TUNER_RADIUS / size[0],
+            TUNER_PIVOT[1] + angle.sin() * radius * TUNER_RADIUS / size[1],
+        ]
+    };
+
+    // a tick every 10 cents, the ones in the center and at the ends are longer
+    for tick in -5..=5 {
+        let tick_cents = tick as f32 * 10.0;
+        let inner = if tick % 5 == 0 { 0.85 } else { 0.92 };
+        let color = gradient_color(theme, 0.5, (tick_cents + 50.0) / 100.0, time);
+        let (mut vertices2, mut indices2) = draw_line(
+            point(tick_cents, inner),
+            point(tick_cents, 1.0),
+            config.width * 0.005,
+            color,
+            vertices.len() as u32,
+            size,
+        );
+        vertices.append(&mut vertices2);
+        indices.append(&mut indices2);
+    }
+
+    if let Some(cents) = cents {
+        let cents = cents.clamp(-50.0, 50.0);
+        // the closer to the note the higher up the gradient
+        let color = gradient_color(theme, 1.0 - cents.abs() / 50.0, (cents + 50.0) / 100.0, time);
+        let (mut vertices2, mut indices2) = draw_line(
+            TUNER_PIVOT,
+            point(cents, 0.95),
+            config.width * 0.01,
+            color,
+            vertices.len() as u32,
+            size,
+        );
+        vertices.append(&mut vertices2);
+        indices.append(&mut indices2);
+    }
+    (vertices, indices)
+}
+
+// part of a bar between the heights `start` and `end` (both 0.0 - 1.0) along its growth axis,
+// centered bars consist of two parts
+fn bar_extents(layout: Layout, start: f32, end: f32) -> Vec<(f32, f32)> {
+    match layout {
+        Layout::Bottom | Layout::Left => vec![(start * 2.0 - 1.0, end * 2.0 - 1.0)],
+        Layout::Top | Layout::Right => vec![(1.0 - start * 2.0, 1.0 - end * 2.0)],
+        Layout::Center => vec![(start, end), (-start, -end)],
+    }
+}
+
+/// appends the meters to the right edge of the mesh, one per channel and one for the loudness,
+/// `pixel_size` as for the text of the overlay, returns their left edge in clip space
+///
+/// channels show their rms as bar, their peak and held peak as marks, red if it clipped,
+/// the loudness shows the momentary loudness as bar and the short-term loudness as mark
+pub fn meters(
+    levels: &Levels,
+    config: &config::Meters,
+    theme: &Theme,
+    time: f32,
+    pixel_size: [f32; 2],
+    vertices: &mut Vec<Vertex>,
+    indices: &mut Vec<u32>,
+) -> f32 {
+    let height = |db: f32| -> f32 {
+        let level = ((db - config.floor) / -config.floor).clamp(0.0, 1.0);
+        METER_BOTTOM + (METER_TOP - METER_BOTTOM) * level
+    };
+
+    let count = levels.channels.len() + 1;
+    let width = METER_WIDTH * pixel_size[0];
+    let right = 1.0 - METER_MARGIN * pixel_size[0];
+    let left = right - (count as f32 * METER_WIDTH + count as f32 * METER_GAP) * pixel_size[0];
+
+    for i in 0..count {
+        // the loudness is separated from the channels by another gap
+        let gap = if i + 1 == count { METER_GAP } else { 0.0 };
+        let x1 = left + (i as f32 * (METER_WIDTH + METER_GAP) + gap) * pixel_size[0];
+        let x2 = x1 + width;
+        let frequency = i as f32 / count as f32;
+        push_quad(
+            vertices,
+            indices,
+            [[x1, METER_BOTTOM], [x2, METER_BOTTOM], [x2, METER_TOP], [x1, METER_TOP]],
+            [METER_TRACK; 4],
+        );
+
+        let (bar, marks) = match levels.channels.get(i) {
+            Some(channel) => {
+                let hold = if channel.hold >= -0.1 { METER_CLIP } else { METER_MARK };
+                (channel.rms, vec![(channel.peak, None), (channel.hold, Some(hold))])
+            }
+            None => (
+                levels.momentary.unwrap_or(crate::meter::MIN_DB),
+                levels.short_term.map(|short_term| (short_term, Some(METER_MARK))).into_iter().collect(),
+            ),
+        };
+
+        let level = |y: f32| (y - METER_BOTTOM) / (METER_TOP - METER_BOTTOM);
+        let top = height(bar);
+        if top > METER_BOTTOM {
+            let bottom_color = gradient_color(theme, 0.0, frequency, time);
+            let top_color = gradient_color(theme, level(top), frequency, time);
+            push_quad(
+                vertices,
+                indices,
+                [[x1, METER_BOTTOM], [x2, METER_BOTTOM], [x2, top], [x1, top]],
+                [bottom_color, bottom_color, top_color, top_color],
+            );
+        }
+        for (db, color) in marks {
+            let y = height(db);
+            if y <= METER_BOTTOM {
+                continue;
+            }
+            let color = color.unwrap_or_else(|| gradient_color(theme, level(y), frequency, time));
+            let y2 = (y - pixel_size[1]).max(METER_BOTTOM);
+            push_quad(vertices, indices, [[x1, y2], [x2, y2], [x2, y], [x1, y]], [color; 4]);
+        }
+    }
+    left
+}
+
+/// horizontal center of key `index` of `len` keys of PianoRoll in clip space
+pub fn piano_key_center(index: usize, len: usize) -> f32 {
+    let slot = 2.0 / len.max(1) as f32;
+    -1.0 + slot * (index as f32 + (1.0 - KEY_GAP) / 2.0)
+}
+
+/// mirrors the buffer, so that low frequencies are in the center
+pub fn mirror(buffer: &[f32]) -> Vec<f32> {
+    buffer.iter().rev().chain(buffer.iter()).copied().collect()
+}
+
+// color of the theme at the given height and frequency (both 0.0 - 1.0), depending on its direction
+fn gradient_color(theme: &Theme, height: f32, frequency: f32, time: f32) -> [f32; 3] {
+    // silent or invalid bins must not produce invalid colors
+    let height = if height.is_finite() { height } else { 0.0 };
+
+    match theme.direction {
+        Direction::Height | Direction::Radial => theme.color(height, time),
+        Direction::Frequency => theme.color(frequency, time),
+    }
+}
+
+// pushes a quad with its corners in order around its edge, regardless of its winding
+fn push_quad(
+    vertices: &mut Vec<Vertex>,
+    indices: &mut Vec<u32>,
+    corners: [[f32; 2]; 4],
+    colors: [[f32; 3]; 4],
+) {
+    let i = vertices.len() as u32;
+    for (corner, color) in corners.iter().zip(colors.iter()) {
+        vertices.push(Vertex { position: [corner[0], corner[1], 0.0], color: *color });
+    }
+
+    // shoelace formula, back faces get culled so clockwise quads have to be reversed
+    let area: f32 = (0..4)
+        .map(|c| {
+            let (a, b) = (corners[c], corners[(c + 1) % 4]);
+            a[0] * b[1] - b[0] * a[1]
+        })
+        .sum();
+    if area >= 0.0 {
+        indices.extend_from_slice(&[i, i+1, i+2, i, i+2, i+3]);
+    } else {
+        indices.extend_from_slice(&[i, i+2, i+1, i, i+3, i+2]);
     }
-    return (vertices, indices);
 }
 
 fn draw_line(
@@ -127,6 +418,10 @@ fn draw_line(
     let dx = x2 - x1;
     let dy = y2 - y1;
     let l = dx.hypot (dy);
+    // points of Inward growth can meet in the center, such a line has no direction
+    if l <= f32::EPSILON {
+        return (vertices, indices);
+    }
     let u = dx * width * 0.5 / l / size[1];
     let v = dy * width * 0.5 / l / size[0];
 
@@ -137,11 +432,279 @@ fn draw_line(
 
     indices.push(vertex_len + 2);
     indices.push(vertex_len + 1);
-    indices.push(vertex_len + 0);
+    indices.push(vertex_len);
     indices.push(vertex_len + 2);
-    indices.push(vertex_len + 0);
+    indices.push(vertex_len);
     indices.push(vertex_len + 3);
 
-    return (vertices, indices);
+    (vertices, indices)
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::theme::{self, ColorStop};
+
+    // black at the bottom and white at the top of the height
+    fn grey() -> Theme {
+        Theme {
+            name: String::from("grey"),
+            stops: vec![
+                ColorStop { position: 0.0, color: [0.0, 0.0, 0.0] },
+                ColorStop { position: 1.0, color: [1.0, 1.0, 1.0] },
+            ],
+            direction: Direction::Height,
+            rainbow: None,
+            background: [0.0, 0.0, 0.0],
+        }
+    }
+
+    fn circle(filled: bool) -> Config {
+        circle_with(config::Circle { filled, ..Config::default().circle })
+    }
+
+    fn circle_with(circle: config::Circle) -> Config {
+        Config {
+            visualisation: Visualisation::Circle,
+            circle,
+            ..Config::default()
+        }
+    }
+
+    // outer points of a filled circle, one per bin, with their radius and angle in degrees
+    fn outer_points(vertices: &[Vertex]) -> Vec<(f32, f32)> {
+        vertices
+            .chunks_exact(4)
+            .map(|quad| {
+                let [x, y, _] = quad[3].position;
+                (x.hypot(y), y.atan2(x).to_degrees().rem_euclid(360.0))
+            })
+            .collect()
+    }
+
+    fn bars_with(bars: config::Bars) -> Config {
+        Config {
+            visualisation: Visualisation::Bars,
+            bars,
+            ..Config::default()
+        }
+    }
+
+    // bounding boxes of the quads as [left, right, bottom, top]
+    fn boxes(vertices: &[Vertex]) -> Vec<[f32; 4]> {
+        vertices
+            .chunks_exact(4)
+            .map(|quad| {
+                let xs = quad.iter().map(|vertex| vertex.position[0]);
+                let ys = quad.iter().map(|vertex| vertex.position[1]);
+                [
+                    xs.clone().fold(f32::INFINITY, f32::min),
+                    xs.fold(f32::NEG_INFINITY, f32::max),
+                    ys.clone().fold(f32::INFINITY, f32::min),
+                    ys.fold(f32::NEG_INFINITY, f32::max),
+                ]
+            })
+            .collect()
+    }
+
+    fn assert_boxes(actual: &[[f32; 4]], expected: &[[f32; 4]]) {
+        assert_eq!(actual.len(), expected.len(), "{:?}", actual);
+        for (a, e) in actual.iter().zip(expected) {
+            assert!(a.iter().zip(e).all(|(a, e)| (a - e).abs() < 1e-5), "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    fn signed_area(vertices: &[Vertex], triangle: &[u32]) -> f32 {
+        let [a, b, c] = [0, 1, 2].map(|i| vertices[triangle[i] as usize].position);
+        (b[0] - a[0]) * (c[1] - a[1]) - (c[0] - a[0]) * (b[1] - a[1])
+    }
+
+    #[test]
+    fn invalid_heights_give_valid_colors() {
+        for theme in theme::all(&[]).iter().chain(std::iter::once(&grey())) {
+            for height in [0.0, f32::NAN, f32::INFINITY, f32::NEG_INFINITY, 5.0] {
+                let color = gradient_color(theme, height, 0.5, 1.0);
+                assert!(color.iter().all(|c| c.is_finite() && (0.0..=1.0).contains(c)), "{} {:?}", theme.name, color);
+            }
+        }
+    }
+
+    #[test]
+    fn circle_colors_are_finite() {
+        let buffer = [0.0, f32::NAN, f32::INFINITY, 1.0, f32::NEG_INFINITY];
+        for filled in [false, true] {
+            let (vertices, _) = from_buffer(&buffer, &circle(filled), &grey(), 0.0, 0.0, [1.0, 1.0]);
+            assert!(!vertices.is_empty());
+            assert!(vertices.iter().all(|vertex| vertex.color.iter().all(|c| c.is_finite())));
+        }
+    }
+
+    #[test]
+    fn circle_colors_match_bars() {
+        // a height of 1.0 is half of the window, so half of the gradient like for Bars and Strings
+        let (vertices, _) = from_buffer(&[1.0, 1.0, 1.0], &circle(false), &grey(), 0.0, 0.0, [1.0, 1.0]);
+        for vertex in vertices {
+            assert_eq!(vertex.color, [0.5, 0.5, 0.5]);
+        }
+    }
+
+    #[test]
+    fn quads_face_the_front_in_both_windings() {
+        let counter_clockwise = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+        let mut clockwise = counter_clockwise;
+        clockwise.reverse();
+        for corners in [counter_clockwise, clockwise] {
+            let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+            push_quad(&mut vertices, &mut indices, corners, [[1.0, 1.0, 1.0]; 4]);
+            assert_eq!(indices.len(), 6);
+            for triangle in indices.chunks_exact(3) {
+                assert!(signed_area(&vertices, triangle) > 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn inward_growth_stays_finite() {
+        // larger than the radius, so that neighbouring points meet in the center
+        let buffer = [5.0, 5.0, 0.0, 5.0, 5.0, 5.0];
+        for filled in [false, true] {
+            let config = circle_with(config::Circle {
+                growth: config::Growth::Inward,
+                mirror: false,
+                filled,
+                ..Config::default().circle
+            });
+            let (vertices, indices) = from_buffer(&buffer, &config, &grey(), 0.0, 0.0, [1.0, 1.0]);
+            assert!(!vertices.is_empty());
+            assert!(vertices.iter().all(|vertex| vertex.position.iter().all(|p| p.is_finite())));
+            assert!(indices.iter().all(|i| (*i as usize) < vertices.len()));
+        }
+    }
+
+    #[test]
+    fn circle_span_and_start() {
+        // a half circle from the right over the top to the left, so both ends are bins
+        let config = circle_with(config::Circle {
+            start_angle: 0.0,
+            span: 180.0,
+            mirror: false,
+            filled: true,
+            ..Config::default().circle
+        });
+        let buffer = [0.0, 0.5, 1.0, 0.5, 0.0];
+        let (vertices, _) = from_buffer(&buffer, &config, &grey(), 0.0, 0.0, [1.0, 1.0]);
+
+        // the last bin only closes the last segment
+        let points = outer_points(&vertices);
+        assert_eq!(points.len(), buffer.len() - 1);
+        for (i, (radius, angle)) in points.iter().enumerate() {
+            assert!((angle - 45.0 * i as f32).abs() < 1e-3, "{} {}", i, angle);
+            assert!((radius - (config.circle.radius + buffer[i])).abs() < 1e-5);
+        }
+        // the inner corner at the end of the last segment
+        let [x, y, _] = vertices[vertices.len() - 3].position;
+        assert!((x + config.circle.radius).abs() < 1e-5 && y.abs() < 1e-5);
+    }
+
+    #[test]
+    fn mirrored_circle_is_symmetric() {
+        let config = circle_with(config::Circle { mirror: true, filled: true, ..Config::default().circle });
+        let buffer = [1.0, 0.5, 0.25, 0.0];
+        let (vertices, _) = from_buffer(&buffer, &config, &grey(), 0.0, 0.0, [1.0, 1.0]);
+
+        // a full circle of the reversed and the original bins, the low frequencies meet at the start
+        let radii: Vec<f32> = outer_points(&vertices).iter().map(|(radius, _)| radius - config.circle.radius).collect();
+        assert_eq!(radii.len(), buffer.len() * 2);
+        for (radius, expected) in radii.iter().zip(mirror(&buffer)) {
+            assert!((radius - expected).abs() < 1e-5, "{:?}", radii);
+        }
+    }
+
+    #[test]
+    fn zero_length_lines_are_skipped() {
+        let (vertices, indices) = draw_line([0.5, 0.5], [0.5, 0.5], 1.0, [1.0; 3], 0, [1.0, 1.0]);
+        assert!(vertices.is_empty() && indices.is_empty());
+        let (vertices, indices) = draw_line([0.0, 0.0], [0.5, 0.0], 1.0, [1.0; 3], 0, [1.0, 1.0]);
+        assert_eq!((vertices.len(), indices.len()), (4, 6));
+    }
+
+    #[test]
+    fn bar_layouts() {
+        let plain = config::Bars { mirror: false, gap: 0.0, min_height: 0.0, ..Config::default().bars };
+        // heights of half and a quarter of the window
+        let buffer = [1.0, 0.5];
+        let expected = [
+            (Layout::Bottom, vec![[-1.0, 0.0, -1.0, 0.0], [0.0, 1.0, -1.0, -0.5]]),
+            (Layout::Top, vec![[-1.0, 0.0, 0.0, 1.0], [0.0, 1.0, 0.5, 1.0]]),
+            (Layout::Left, vec![[-1.0, 0.0, -1.0, 0.0], [-1.0, -0.5, 0.0, 1.0]]),
+            (Layout::Right, vec![[0.0, 1.0, -1.0, 0.0], [0.5, 1.0, 0.0, 1.0]]),
+            // from the middle in both directions
+            (Layout::Center, vec![
+                [-1.0, 0.0, 0.0, 0.5],
+                [-1.0, 0.0, -0.5, 0.0],
+                [0.0, 1.0, 0.0, 0.25],
+                [0.0, 1.0, -0.25, 0.0],
+            ]),
+        ];
+        for (layout, expected) in expected {
+            let config = bars_with(config::Bars { layout, ..plain });
+            let (vertices, indices) = from_buffer(&buffer, &config, &grey(), 0.0, 0.0, [1.0, 1.0]);
+            assert_boxes(&boxes(&vertices), &expected);
+            assert_eq!(indices.len(), expected.len() * 6);
+        }
+    }
+
+    #[test]
+    fn bar_gaps() {
+        let config = bars_with(config::Bars {
+            layout: Layout::Bottom,
+            mirror: false,
+            gap: 0.5,
+            min_height: 0.0,
+        });
+        let (vertices, _) = from_buffer(&[1.0; 4], &config, &grey(), 0.0, 0.0, [1.0, 1.0]);
+        let boxes = boxes(&vertices);
+        // half of every slot of 0.5 is filled, centered in the slot
+        assert_boxes(&boxes, &[
+            [-0.875, -0.625, -1.0, 0.0],
+            [-0.375, -0.125, -1.0, 0.0],
+            [0.125, 0.375, -1.0, 0.0],
+            [0.625, 0.875, -1.0, 0.0],
+        ]);
+        for pair in boxes.windows(2) {
+            assert!(pair[1][0] - pair[0][1] > 0.25 - 1e-5);
+        }
+    }
+
+    #[test]
+    fn bars_have_a_minimum_height() {
+        let config = bars_with(config::Bars {
+            layout: Layout::Bottom,
+            mirror: false,
+            gap: 0.0,
+            min_height: 0.05,
+        });
+        let (vertices, _) = from_buffer(&[0.0, -1.0, f32::NAN, 1.0], &config, &grey(), 0.0, 0.0, [1.0, 1.0]);
+        let tops: Vec<f32> = boxes(&vertices).iter().map(|quad| quad[3]).collect();
+        assert_eq!(tops, [-0.9, -0.9, -0.9, 0.0]);
+    }
+
+    #[test]
+    fn mirrored_bars_are_symmetric() {
+        let config = bars_with(config::Bars {
+            layout: Layout::Bottom,
+            mirror: true,
+            gap: 0.0,
+            min_height: 0.0,
+        });
+        let (vertices, _) = from_buffer(&[1.0, 0.5, 0.0], &config, &grey(), 0.0, 0.0, [1.0, 1.0]);
+        let boxes = boxes(&vertices);
+        // the low frequencies meet in the center
+        let tops: Vec<f32> = boxes.iter().map(|quad| quad[3]).collect();
+        assert_eq!(tops, [-1.0, -0.5, 0.0, 0.0, -0.5, -1.0]);
+        for (left, right) in boxes.iter().zip(boxes.iter().rev()) {
+            assert!((left[0] + right[1]).abs() < 1e-5 && (left[1] + right[0]).abs() < 1e-5, "{:?}", boxes);
+        }
+    }
+}
@@ -1,2 +1,12 @@
-pub(crate) mod wgpu_abstraction;
-pub(crate) mod mesh;
+mod wgpu_abstraction;
+pub mod visualiser;
+mod capture;
+pub mod offscreen;
+// the window of the binary, embedders use `Visualiser` instead
+#[doc(hidden)]
+pub mod window;
+pub mod mesh;
+mod text;
+pub mod theme;
+pub mod scene;
+pub use capture::Frame;
@@ -0,0 +1,109 @@
+use crate::config::{Action, Config};
+use crate::graphics::capture::{self, Capture, Frame};
+use crate::graphics::scene::Inputs;
+use crate::graphics::visualiser::Visualiser;
+use crate::renderer::{Renderer, Spectrum};
+use colored::*;
+
+// copying frames back supports rgba and bgra, the srgb variant matches what windows show
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// renders the visualisation into a texture without any window,
+/// the frames can be recorded like the recordings of the window
+pub struct Offscreen {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    size: [u32; 2],
+    visualiser: Visualiser,
+    capture: Capture,
+    frame: Option<Frame>,
+}
+
+impl Offscreen {
+    /// fails if there is no adapter that can render without a surface
+    pub async fn new(inputs: Inputs, config: Config, size: [u32; 2]) -> Result<Self, String> {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let adapter = instance.request_adapter(
+            &wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface: None,
+            },
+        ).await.ok_or_else(|| String::from("no graphics adapter found"))?;
+
+        let adapter_info = adapter.get_info();
+        println!(
+            "using adapter: {} ({:?})",
+            adapter_info.name.green().bold(),
+            adapter_info.backend,
+        );
+
+        let (device, queue) = adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                features: wgpu::Features::empty(),
+                limits: wgpu::Limits::default(),
+                label: None,
+            },
+            None,
+        ).await.map_err(|e| format!("failed to request device: {}", e))?;
+
+        let capture = Capture::new(config.capture.clone());
+        let visualiser = Visualiser::new(&device, FORMAT, inputs, config);
+
+        Ok(Self {
+            device,
+            queue,
+            size,
+            visualiser,
+            capture,
+            frame: None,
+        })
+    }
+
+    /// records every rendered frame into the capture directory, returns the path of the recording
+    pub fn start_recording(&mut self) -> Result<std::path::PathBuf, String> {
+        self.capture.start_recording()
+    }
+
+    /// the last rendered frame, unless it was handed to the recording
+    pub fn frame(&self) -> Option<&Frame> {
+        self.frame.as_ref()
+    }
+
+    pub fn visualiser(&self) -> &Visualiser {
+        &self.visualiser
+    }
+}
+
+impl Renderer for Offscreen {
+    type Error = String;
+
+    fn update(&mut self, spectrum: &Spectrum) {
+        self.visualiser.update(&self.device, spectrum, self.size);
+    }
+
+    fn render(&mut self) -> Result<(), String> {
+        let frame = capture::read_frame(&self.device, &self.queue, &self.visualiser, FORMAT, self.size)?;
+        if self.capture.recording() {
+            self.frame = None;
+            self.capture.record(frame);
+        } else {
+            self.frame = Some(frame);
+        }
+        Ok(())
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        if width > 0 && height > 0 {
+            self.size = [width, height];
+        }
+    }
+
+    fn input(&mut self, action: Action) -> bool {
+        self.visualiser.input(action);
+        true
+    }
+
+    fn animating(&self) -> bool {
+        self.visualiser.animating()
+    }
+}
@@ -0,0 +1,480 @@
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+use crate::agc::AutoGain;
+use crate::audio::{AudioError, DeviceSwitch};
+use crate::beat::Beat;
+use crate::amplitude::BinScale;
+use crate::config::{Action, AmplitudeMode, Config, Engine, Visualisation};
+use crate::graphics::mesh::{self, Vertex};
+use crate::graphics::text;
+use crate::graphics::theme::Theme;
+use crate::idle::Activity;
+use crate::notes;
+use crate::meter::{self, Levels, MeterState};
+use crate::pitch::PitchState;
+use crate::renderer::Spectrum;
+
+// spectra older than two refreshes of audioviz, and at least this, are stale,
+// then audioviz is stuck or fetching paused
+const STALE_AGE: Duration = Duration::from_millis(250);
+const STALE_REFRESHES: f32 = 2.0;
+// time constant in seconds of the bars falling while the spectrum is stale
+const STALE_DECAY: f32 = 0.2;
+// how long notifications stay on screen
+const NOTIFICATION_DURATION: Duration = Duration::from_millis(1500);
+// how long Tuner keeps showing the last note after the pitch got lost
+const PITCH_HOLD: Duration = Duration::from_millis(500);
+// time constant of the needle of Tuner in seconds
+const NEEDLE_SMOOTHING: f32 = 0.1;
+
+// nearest note of the last pitch and how far off it is
+struct Tuning {
+    note: u8,
+    frequency: f32,
+    // smoothed, jumps when the note changes
+    cents: f32,
+}
+
+/// everything a scene reads besides its configuration, `AudioSource::inputs` provides all of them
+///
+/// applications that capture and analyse the audio themselves only need a sender
+/// that answers the events of audioviz, everything missing is just not shown
+pub struct Inputs {
+    // to request the spectrum from and to change the configuration of audioviz
+    pub event_sender: mpsc::Sender<audioviz::Event>,
+    // whether the device is connected, always connected without
+    pub activity: Option<Arc<Activity>>,
+    pub beats: Option<mpsc::Receiver<Beat>>,
+    pub pitch: Option<Arc<PitchState>>,
+    pub meters: Option<Arc<MeterState>>,
+    // for the NextDevice action
+    pub devices: Option<DeviceSwitch>,
+}
+
+impl Inputs {
+    pub fn new(event_sender: mpsc::Sender<audioviz::Event>) -> Self {
+        Self {
+            event_sender,
+            activity: None,
+            beats: None,
+            pitch: None,
+            meters: None,
+            devices: None,
+        }
+    }
+}
+
+/// state of the visualisation that is shared by every renderer:
+/// processing of the spectrum, beats, themes, key actions and the overlay
+pub struct Scene {
+    pub config: Config,
+    event_sender: mpsc::Sender<audioviz::Event>,
+    beats: Option<mpsc::Receiver<Beat>>,
+    activity: Option<Arc<Activity>>,
+    devices: Option<DeviceSwitch>,
+    // switch of the audio thread that has not finished yet
+    switching: Option<mpsc::Receiver<Result<String, AudioError>>>,
+    pitch: Option<Arc<PitchState>>,
+    tuning: Option<Tuning>,
+    last_pitch: Instant,
+    meters: Option<Arc<MeterState>>,
+    levels: Option<Levels>,
+    auto_gain: AutoGain,
+    last_update: Instant,
+    // last spectrum, scaled into heights
+    buffer: Vec<f32>,
+    frozen: bool,
+    show_overlay: bool,
+    show_meters: bool,
+    themes: Vec<Theme>,
+    theme: usize,
+    start: Instant,
+    notification: Option<(String, Instant)>,
+    // strength of the reaction to the last beat, fades from 1.0 to 0.0
+    pulse: f32,
+    bpm: Option<f32>,
+}
+
+impl Scene {
+    pub fn new(inputs: Inputs, config: Config) -> Self {
+        let themes = config.all_themes();
+        let theme = themes.iter().position(|theme| theme.name == config.theme).unwrap_or(0);
+
+        Self {
+            event_sender: inputs.event_sender,
+            beats: inputs.beats,
+            activity: inputs.activity,
+            devices: inputs.devices,
+            switching: None,
+            pitch: inputs.pitch,
+            tuning: None,
+            last_pitch: Instant::now(),
+            meters: inputs.meters,
+            levels: None,
+            auto_gain: AutoGain::new(config.agc),
+            show_meters: config.meters.enabled,
+            config,
+            last_update: Instant::now(),
+            buffer: Vec::new(),
+            frozen: false,
+            show_overlay: true,
+            theme,
+            themes,
+            start: Instant::now(),
+            notification: None,
+            pulse: 0.0,
+            bpm: None,
+        }
+    }
+
+    pub fn handle_action(&mut self, action: Action) {
+        let decibel = self.config.amplitude.mode == AmplitudeMode::Decibel;
+        let notification = match action {
+            // calibrated levels must not depend on the volume or the gain control
+            Action::VolumeUp | Action::VolumeDown if decibel => {
+                String::from("volume is not used in Decibel mode, see db_offset")
+            }
+            Action::ToggleAutoGain if decibel => String::from("auto gain is not used in Decibel mode"),
+            Action::VolumeUp | Action::VolumeDown => {
+                if action == Action::VolumeUp {
+                    self.config.audio.volume *= 1.1;
+                } else {
+                    self.config.audio.volume /= 1.1;
+                }
+                self.send_audio_config();
+                format!("volume: {:.2}", self.config.audio.volume)
+            }
+            // the calibrated engines do not smooth over neighbouring frequencies
+            Action::SmoothingUp | Action::SmoothingDown if self.config.analysis.engine != Engine::Audioviz => {
+                format!("smoothing is not used by the {:?} engine", self.config.analysis.engine)
+            }
+            Action::SmoothingUp | Action::SmoothingDown => {
+                let smoothing = &mut self.config.audio.smoothing_amount;
+                if action == Action::SmoothingUp {
+                    *smoothing += 1;
+                } else {
+                    *smoothing = smoothing.saturating_sub(1);
+                }
+                self.send_audio_config();
+                format!("smoothing: {}", self.config.audio.smoothing_amount)
+            }
+            Action::NextVisualisation | Action::PreviousVisualisation => {
+                self.config.visualisation = if action == Action::NextVisualisation {
+                    self.config.visualisation.next()
+                } else {
+                    self.config.visualisation.previous()
+                };
+                format!("{:?}", self.config.visualisation)
+            }
+            Action::NextTheme => {
+                self.theme = (self.theme + 1) % self.themes.len();
+                format!("theme: {}", self.themes[self.theme].name)
+            }
+            Action::Freeze => {
+                self.frozen = !self.frozen;
+                String::from(if self.frozen { "frozen" } else { "unfrozen" })
+            }
+            Action::ToggleOverlay => {
+                self.show_overlay = !self.show_overlay;
+                String::from(if self.show_overlay { "overlay on" } else { "overlay off" })
+            }
+            Action::ToggleAutoGain => {
+                let state = if self.auto_gain.toggle() { "on" } else { "off" };
+                format!("auto gain: {}", state)
+            }
+            Action::ToggleMeters if self.meters.is_none() => String::from("meters are not available"),
+            Action::ToggleMeters => {
+                self.show_meters = !self.show_meters;
+                String::from(if self.show_meters { "meters on" } else { "meters off" })
+            }
+            Action::NextDevice => match self.devices.as_ref().map(DeviceSwitch::cycle) {
+                // opening a device can take a while, `update` shows the result
+                Some(switching) => {
+                    self.switching = Some(switching);
+                    String::from("switching device...")
+                }
+                None => String::from("switching devices is not available"),
+            },
+            // only renderers can read their frames back
+            Action::Screenshot | Action::ToggleRecording => return,
+        };
+        self.notify(notification);
+    }
+
+    /// shows a message on screen for a short time
+    pub fn notify(&mut self, message: String) {
+        self.notification = Some((message, Instant::now()));
+    }
+
+    fn send_audio_config(&self) {
+        // fails only if the analysis stopped, in which case there is nothing to configure
+        self.event_sender.send(audioviz::Event::SendConfig(self.config.audio.to_audioviz())).ok();
+    }
+
+    /// returns true while something on screen changes without new audio data
+    pub fn animating(&self) -> bool {
+        self.notification.is_some() || self.switching.is_some()
+    }
+
+    /// takes the raw spectrum of the current frame, which gets ignored while frozen,
+    /// stale spectra are not shown as they are, instead the last bars fall
+    pub fn update(&mut self, spectrum: &Spectrum) {
+        let now = Instant::now();
+        let delta = (now - self.last_update).as_secs_f32();
+        self.last_update = now;
+
+        if self.config.beat.decay > 0.0 {
+            self.pulse *= (-delta / self.config.beat.decay).exp();
+        } else {
+            self.pulse = 0.0;
+        }
+        for beat in self.beats.iter().flat_map(|beats| beats.try_iter()) {
+            self.pulse = 1.0;
+            self.bpm = beat.bpm;
+        }
+
+        if let Some((_, shown)) = &self.notification {
+            if shown.elapsed() > NOTIFICATION_DURATION {
+                self.notification = None;
+            }
+        }
+        if let Some(switching) = &self.switching {
+            let notification = match switching.try_recv() {
+                Ok(Ok(name)) => Some(format!("device: {}", name)),
+                Ok(Err(e)) => Some(e.to_string()),
+                Err(mpsc::TryRecvError::Empty) => None,
+                Err(mpsc::TryRecvError::Disconnected) => Some(AudioError::Disconnected.to_string()),
+            };
+            if let Some(notification) = notification {
+                self.switching = None;
+                self.notify(notification);
+            }
+        }
+
+        let connected = self.connected();
+        if !connected {
+            // audioviz keeps the last spectrum of the disconnected device
+            self.buffer.clear();
+        } else if self.frozen {
+            // keeps showing the last bars
+        } else if spectrum.age() > self.stale_age() {
+            let decay = (-delta / STALE_DECAY).exp();
+            self.buffer.iter_mut().for_each(|value| *value *= decay);
+        } else {
+            let mut buffer = spectrum.bins.clone();
+            // Decibel mode shows the calibrated levels of the engine as they are
+            if self.config.amplitude.mode == AmplitudeMode::Power {
+                // audioviz applies the volume itself, the calibrated engines leave it to the display
+                if self.config.analysis.engine != Engine::Audioviz {
+                    buffer.iter_mut().for_each(|value| *value *= self.config.audio.volume);
+                }
+                self.auto_gain.process(&mut buffer, delta);
+            }
+            let bin_scale = self.config.bin_scale();
+            crate::amplitude::scale_buffer(&mut buffer, &self.config.amplitude, bin_scale);
+            // every other engine has to be mapped onto the keys
+            if self.config.visualisation == Visualisation::PianoRoll && bin_scale != BinScale::Notes {
+                let len = buffer.len();
+                buffer = notes::from_spectrum(&buffer, |i| bin_scale.frequency(i, len));
+            }
+            self.buffer = buffer;
+        }
+
+        // the levels only get measured while they are shown
+        if let Some(meters) = &self.meters {
+            meters.set_active(self.show_meters);
+            if !self.show_meters || !connected {
+                self.levels = None;
+            } else if !self.frozen {
+                self.levels = meters.get();
+            }
+        }
+
+        // the pitch only gets detected while it is shown
+        let tuner = self.config.visualisation == Visualisation::Tuner;
+        if let Some(pitch) = &self.pitch {
+            pitch.set_active(tuner);
+        }
+        if !tuner || !connected {
+            self.tuning = None;
+        } else if !self.frozen {
+            self.update_tuning(now, delta);
+        }
+    }
+
+    fn update_tuning(&mut self, now: Instant, delta: f32) {
+        let pitch = match self.pitch.as_ref().and_then(|pitch| pitch.get()) {
+            Some(pitch) => pitch,
+            None => {
+                if now - self.last_pitch > PITCH_HOLD {
+                    self.tuning = None;
+                }
+                return;
+            }
+        };
+        self.last_pitch = now;
+
+        let note = notes::from_frequency(pitch.frequency * 440.0 / self.config.tuner.reference);
+        let nearest = note.round();
+        let cents = (note - nearest) * 100.0;
+        let nearest = nearest.clamp(0.0, 127.0) as u8;
+
+        let cents = match &self.tuning {
+            Some(tuning) if tuning.note == nearest => {
+                tuning.cents + (cents - tuning.cents) * (1.0 - (-delta / NEEDLE_SMOOTHING).exp())
+            }
+            _ => cents,
+        };
+        self.tuning = Some(Tuning { note: nearest, frequency: pitch.frequency, cents });
+    }
+
+    fn stale_age(&self) -> Duration {
+        let refresh = Duration::from_secs_f32(STALE_REFRESHES / self.config.audio.refresh_rate.max(1) as f32);
+        refresh.max(STALE_AGE)
+    }
+
+    // without activity there is nothing that could disconnect
+    fn connected(&self) -> bool {
+        self.activity.as_ref().is_none_or(|activity| activity.connected())
+    }
+
+    /// how many cents the pitch is off its nearest note for Tuner, None without a pitch
+    pub fn cents(&self) -> Option<f32> {
+        self.tuning.as_ref().map(|tuning| tuning.cents)
+    }
+
+    /// last spectrum, scaled into heights
+    pub fn buffer(&self) -> &[f32] {
+        &self.buffer
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.themes[self.theme]
+    }
+
+    /// seconds since the start, used for animated themes
+    pub fn time(&self) -> f32 {
+        self.start.elapsed().as_secs_f32()
+    }
+
+    pub fn pulse(&self) -> f32 {
+        self.pulse
+    }
+
+    /// background color of the theme, flashing on beats
+    pub fn background(&self) -> [f32; 3] {
+        let flash = self.pulse * self.config.beat.flash;
+        self.theme().background.map(|c| (c + flash).min(1.0))
+    }
+
+    /// mesh of the visualisation, see `mesh::from_buffer` for `size`
+    pub fn mesh(&self, size: [f32; 2]) -> (Vec<Vertex>, Vec<u32>) {
+        match self.config.visualisation {
+            Visualisation::Tuner => mesh::tuner(self.cents(), &self.config, self.theme(), self.time(), size),
+            _ => mesh::from_buffer(&self.buffer, &self.config, self.theme(), self.time(), self.pulse, size),
+        }
+    }
+
+    /// appends notifications, the bpm and a missing signal to the mesh,
+    /// `pixel_size` is the size of one pixel of the text in clip space
+    pub fn overlay(&self, pixel_size: [f32; 2], vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>) {
+        let mut texts: Vec<(String, [f32; 2])> = Vec::new();
+
+        // shown even without overlay, otherwise the screen would just be empty
+        if !self.connected() {
+            let text = String::from("no signal");
+            let position = [
+                -pixel_size[0] * text::width(&text) as f32 / 2.0,
+                pixel_size[1] * 7.0 / 2.0,
+            ];
+            texts.push((text, position));
+        }
+
+        if self.show_overlay {
+            if let Some((notification, _)) = &self.notification {
+                let position = [-1.0 + pixel_size[0] * 4.0, 1.0 - pixel_size[1] * 4.0];
+                texts.push((notification.clone(), position));
+            }
+            if let Some(bpm) = self.bpm {
+                let bpm = format!("{:.0} bpm", bpm);
+                let position = [
+                    1.0 - pixel_size[0] * (4 + text::width(&bpm)) as f32,
+                    1.0 - pixel_size[1] * 4.0,
+                ];
+                texts.push((bpm, position));
+            }
+        }
+
+        let mut texts: Vec<(String, [f32; 2], [f32; 3])> =
+            texts.into_iter().map(|(text, position)| (text, position, [1.0, 1.0, 1.0])).collect();
+
+        // names of the C keys on the keyboard, part of the visualisation
+        if self.config.visualisation == Visualisation::PianoRoll && !self.buffer.is_empty() {
+            for (i, note) in (notes::LOWEST..=notes::HIGHEST).enumerate().filter(|(_, note)| note % 12 == 0) {
+                let name = notes::name(note);
+                let position = [
+                    mesh::piano_key_center(i, self.buffer.len()) - pixel_size[0] * text::width(&name) as f32 / 2.0,
+                    -1.0 + mesh::KEYBOARD_HEIGHT - pixel_size[1] * 2.0,
+                ];
+                texts.push((name, position, [0.1, 0.1, 0.1]));
+            }
+        }
+
+        // meters on the right edge with their readings left of their bottom
+        if let Some(levels) = &self.levels {
+            let left = mesh::meters(levels, &self.config.meters, self.theme(), self.time(), pixel_size, vertices, indices);
+            let reading = |value: Option<f32>| match value {
+                Some(value) if value > meter::MIN_DB => format!("{:.1}", value),
+                Some(_) => String::from("-inf"),
+                None => String::from("-"),
+            };
+            let readings = [
+                format!("M {} LUFS", reading(levels.momentary)),
+                format!("S {} LUFS", reading(levels.short_term)),
+                format!("I {} LUFS", reading(levels.integrated)),
+                format!("TP {} dBTP", reading(Some(levels.true_peak))),
+            ];
+            for (i, text) in readings.iter().enumerate() {
+                let position = [
+                    left - pixel_size[0] * (4 + text::width(text)) as f32,
+                    mesh::METER_BOTTOM + pixel_size[1] * (7.0 + 9.0 * (readings.len() - 1 - i) as f32),
+                ];
+                texts.push((text.clone(), position, [1.0, 1.0, 1.0]));
+            }
+        }
+
+        // note below the needle of Tuner, as large as there is room for
+        let mut large_texts: Vec<(String, [f32; 2], [f32; 2])> = Vec::new();
+        if let Some(tuning) = self.tuning.as_ref().filter(|_| self.config.visualisation == Visualisation::Tuner) {
+            let scale = (0.25 / (pixel_size[1] * 7.0)).floor().clamp(1.0, 3.0);
+            let large = pixel_size.map(|size| size * scale);
+            let name = notes::name(tuning.note);
+            let top = mesh::TUNER_PIVOT[1] - pixel_size[1] * 8.0;
+            let position = [mesh::TUNER_PIVOT[0] - large[0] * text::width(&name) as f32 / 2.0, top];
+            large_texts.push((name, position, large));
+
+            let details = format!("{:+.0} cents  {:.1} hz", tuning.cents, tuning.frequency);
+            let position = [
+                mesh::TUNER_PIVOT[0] - pixel_size[0] * text::width(&details) as f32 / 2.0,
+                top - large[1] * 7.0 - pixel_size[1] * 6.0,
+            ];
+            texts.push((details, position, [1.0, 1.0, 1.0]));
+        }
+
+        let texts = large_texts
+            .into_iter()
+            .map(|(text, position, size)| (text, position, size, [1.0, 1.0, 1.0]))
+            .chain(texts.into_iter().map(|(text, position, color)| (text, position, pixel_size, color)));
+        for (string, position, pixel_size, color) in texts {
+            let (mut text_vertices, mut text_indices) = text::from_str(
+                &string,
+                position,
+                pixel_size,
+                color,
+                vertices.len() as u32,
+            );
+            vertices.append(&mut text_vertices);
+            indices.append(&mut text_indices);
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Direction {
+    // from the bottom to the top of each bar or line
+    #[default]
+    Height,
+    // from the left to the right of the spectrum, around the circle for Circle
+    Frequency,
+    // from the inner to the outer edge of Circle, same as Height otherwise
+    Radial,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ColorStop {
+    pub position: f32,
+    pub color: [f32; 3],
+}
+
+/// replaces the color stops with a hsv rainbow that cycles over time
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Rainbow {
+    // hue rotations per second
+    pub speed: f32,
+    // hue rotations over the whole gradient
+    pub spread: f32,
+    pub saturation: f32,
+    pub value: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Theme {
+    pub name: String,
+    #[serde(default)]
+    pub stops: Vec<ColorStop>,
+    #[serde(default)]
+    pub direction: Direction,
+    #[serde(default)]
+    pub rainbow: Option<Rainbow>,
+    #[serde(default)]
+    pub background: [f32; 3],
+}
+
+impl Theme {
+    /// color at `position` of the gradient (0.0 - 1.0), `time` is in seconds
+    pub fn color(&self, position: f32, time: f32) -> [f32; 3] {
+        let position = position.clamp(0.0, 1.0);
+
+        if let Some(rainbow) = self.rainbow {
+            let hue = (position * rainbow.spread + time * rainbow.speed).rem_euclid(1.0);
+            return hsv_to_rgb(hue, rainbow.saturation, rainbow.value);
+        }
+
+        let (first, last) = match (self.stops.first(), self.stops.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return [1.0, 1.0, 1.0],
+        };
+        if position <= first.position {
+            return first.color;
+        }
+        if position >= last.position {
+            return last.color;
+        }
+
+        for stops in self.stops.windows(2) {
+            let (start, end) = (stops[0], stops[1]);
+            if position <= end.position {
+                let range = end.position - start.position;
+                let t = if range > 0.0 { (position - start.position) / range } else { 1.0 };
+                return [
+                    start.color[0] + (end.color[0] - start.color[0]) * t,
+                    start.color[1] + (end.color[1] - start.color[1]) * t,
+                    start.color[2] + (end.color[2] - start.color[2]) * t,
+                ];
+            }
+        }
+        last.color
+    }
+
+    /// positions of the color stops between `start` and `end` of a height gradient,
+    /// used to split geometry so that every stop is visible
+    pub fn stops_between(&self, start: f32, end: f32) -> impl Iterator<Item = f32> + '_ {
+        let stops: &[ColorStop] = if self.rainbow.is_some() || self.direction == Direction::Frequency {
+            &[]
+        } else {
+            &self.stops
+        };
+        stops.iter()
+            .map(|stop| stop.position)
+            .filter(move |position| *position > start && *position < end)
+    }
+
+    fn sort_stops(&mut self) {
+        self.stops.sort_by(|a, b| a.position.partial_cmp(&b.position).unwrap_or(std::cmp::Ordering::Equal));
+    }
+}
+
+/// built in themes followed by the user defined ones,
+/// user themes replace built in ones with the same name
+pub fn all(user_themes: &[Theme]) -> Vec<Theme> {
+    let mut themes: Vec<Theme> = builtin()
+        .into_iter()
+        .filter(|theme| !user_themes.iter().any(|user| user.name == theme.name))
+        .collect();
+    themes.extend(user_themes.iter().cloned());
+
+    for theme in themes.iter_mut() {
+        theme.sort_stops();
+    }
+    themes
+}
+
+pub fn builtin() -> Vec<Theme> {
+    vec![
+        gradient("classic", Direction::Height, &[
+            (0.0, [0.0, 0.0, 0.05]),
+            (1.0, [1.0, 0.0, 0.0]),
+        ]),
+        gradient("fire", Direction::Height, &[
+            (0.0, [0.1, 0.0, 0.0]),
+            (0.3, [0.9, 0.1, 0.0]),
+            (0.6, [1.0, 0.6, 0.0]),
+            (1.0, [1.0, 1.0, 0.6]),
+        ]),
+        gradient("ocean", Direction::Height, &[
+            (0.0, [0.0, 0.05, 0.1]),
+            (0.5, [0.0, 0.4, 0.8]),
+            (1.0, [0.6, 1.0, 1.0]),
+        ]),
+        gradient("neon", Direction::Frequency, &[
+            (0.0, [1.0, 0.0, 0.8]),
+            (0.5, [0.3, 0.0, 1.0]),
+            (1.0, [0.0, 1.0, 0.9]),
+        ]),
+        gradient("mono", Direction::Height, &[
+            (0.0, [0.1, 0.1, 0.1]),
+            (1.0, [1.0, 1.0, 1.0]),
+        ]),
+        Theme {
+            name: String::from("rainbow"),
+            stops: Vec::new(),
+            direction: Direction::Frequency,
+            rainbow: Some(Rainbow {
+                speed: 0.1,
+                spread: 1.0,
+                saturation: 1.0,
+                value: 1.0,
+            }),
+            background: [0.0, 0.0, 0.0],
+        },
+    ]
+}
+
+fn gradient(name: &str, direction: Direction, stops: &[(f32, [f32; 3])]) -> Theme {
+    Theme {
+        name: String::from(name),
+        stops: stops.iter().map(|(position, color)| ColorStop { position: *position, color: *color }).collect(),
+        direction,
+        rainbow: None,
+        background: [0.0, 0.0, 0.0],
+    }
+}
+
+/// `hue`, `saturation` and `value` in the range of 0.0 - 1.0
+pub fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> [f32; 3] {
+    let h = hue.rem_euclid(1.0) * 6.0;
+    let c = value * saturation;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let m = value - c;
+
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    [r + m, g + m, b + m]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_color(actual: [f32; 3], expected: [f32; 3]) {
+        assert!(actual.iter().zip(expected.iter()).all(|(a, e)| (a - e).abs() < 1e-5), "{:?} != {:?}", actual, expected);
+    }
+
+    fn fire() -> Theme {
+        builtin().into_iter().find(|theme| theme.name == "fire").unwrap()
+    }
+
+    #[test]
+    fn colors_at_and_between_stops() {
+        let theme = fire();
+        for stop in &theme.stops {
+            assert_color(theme.color(stop.position, 0.0), stop.color);
+        }
+        // halfway between the stops at 0.3 and 0.6
+        assert_color(theme.color(0.45, 0.0), [0.95, 0.35, 0.0]);
+        assert_color(theme.color(0.15, 0.0), [0.5, 0.05, 0.0]);
+    }
+
+    #[test]
+    fn positions_are_clamped() {
+        let theme = fire();
+        assert_color(theme.color(-1.0, 0.0), [0.1, 0.0, 0.0]);
+        assert_color(theme.color(2.0, 0.0), [1.0, 1.0, 0.6]);
+        assert_color(theme.color(f32::INFINITY, 0.0), [1.0, 1.0, 0.6]);
+
+        // stops that do not reach the ends keep their color up to the ends
+        let theme = gradient("inner", Direction::Height, &[(0.25, [1.0, 0.0, 0.0]), (0.75, [0.0, 0.0, 1.0])]);
+        assert_color(theme.color(0.1, 0.0), [1.0, 0.0, 0.0]);
+        assert_color(theme.color(0.9, 0.0), [0.0, 0.0, 1.0]);
+        assert_color(theme.color(0.5, 0.0), [0.5, 0.0, 0.5]);
+    }
+
+    #[test]
+    fn primary_hues() {
+        assert_color(hsv_to_rgb(0.0, 1.0, 1.0), [1.0, 0.0, 0.0]);
+        assert_color(hsv_to_rgb(1.0 / 6.0, 1.0, 1.0), [1.0, 1.0, 0.0]);
+        assert_color(hsv_to_rgb(1.0 / 3.0, 1.0, 1.0), [0.0, 1.0, 0.0]);
+        assert_color(hsv_to_rgb(0.5, 1.0, 1.0), [0.0, 1.0, 1.0]);
+        assert_color(hsv_to_rgb(2.0 / 3.0, 1.0, 1.0), [0.0, 0.0, 1.0]);
+        assert_color(hsv_to_rgb(5.0 / 6.0, 1.0, 1.0), [1.0, 0.0, 1.0]);
+        // hues wrap around, no saturation is grey
+        assert_color(hsv_to_rgb(1.0, 1.0, 1.0), [1.0, 0.0, 0.0]);
+        assert_color(hsv_to_rgb(-1.0 / 3.0, 1.0, 0.5), [0.0, 0.0, 0.5]);
+        assert_color(hsv_to_rgb(0.3, 0.0, 0.5), [0.5, 0.5, 0.5]);
+    }
+
+    #[test]
+    fn stops_between_the_range() {
+        let theme = fire();
+        assert_eq!(theme.stops_between(0.0, 1.0).collect::<Vec<_>>(), [0.3, 0.6]);
+        assert_eq!(theme.stops_between(0.3, 0.7).collect::<Vec<_>>(), [0.6]);
+        assert_eq!(theme.stops_between(0.0, 0.2).count(), 0);
+
+        // gradients that do not follow the height are not split
+        let neon = builtin().into_iter().find(|theme| theme.name == "neon").unwrap();
+        assert_eq!(neon.stops_between(0.0, 1.0).count(), 0);
+        let rainbow = builtin().into_iter().find(|theme| theme.name == "rainbow").unwrap();
+        assert_eq!(rainbow.stops_between(0.0, 1.0).count(), 0);
+    }
+}
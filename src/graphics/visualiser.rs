@@ -0,0 +1,226 @@
+use wgpu::util::DeviceExt;
+use crate::config::{Action, Config};
+use crate::graphics::mesh::Vertex;
+use crate::graphics::scene::{Inputs, Scene};
+use crate::renderer::Spectrum;
+
+// size of one pixel of the on screen text in physical pixels
+const TEXT_SCALE: f32 = 3.0;
+
+// layout of `Vertex` in the vertex buffer
+fn vertex_layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+    wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &[
+            wgpu::VertexAttribute {
+                offset: 0,
+                shader_location: 0,
+                format: wgpu::VertexFormat::Float32x3,
+            },
+            wgpu::VertexAttribute {
+                offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                shader_location: 1,
+                format: wgpu::VertexFormat::Float32x3,
+            }
+        ]
+    }
+}
+
+/// draws the visualisation with wgpu, independent of any window,
+/// so it can be embedded into other wgpu applications
+pub struct Visualiser {
+    render_pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    num_indices: u32,
+    index_buffer: wgpu::Buffer,
+    scene: Scene,
+}
+
+impl Visualiser {
+    /// `format` has to be the format of the texture that gets rendered to,
+    /// render passes drawn into must not use multisampling or a depth buffer
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        inputs: Inputs,
+        config: Config,
+    ) -> Self {
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Shader"),
+            //flags: wgpu::ShaderFlags::all(), // very weird behavior, could be the fault of rust nightly but I am not sure
+            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
+        });
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Render Pipeline Layout"),
+                bind_group_layouts: &[],
+                push_constant_ranges: &[],
+            });
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Render Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "main", // 1.
+                buffers: &[
+                    vertex_layout(),
+                ],
+            },
+            fragment: Some(wgpu::FragmentState { // 3.
+                module: &shader,
+                entry_point: "main",
+                targets: &[wgpu::ColorTargetState { // 4.
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList, // 1.
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw, // 2.
+                cull_mode: Some(wgpu::Face::Back),
+                // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
+                polygon_mode: wgpu::PolygonMode::Fill,
+                // Requires Features::DEPTH_CLAMPING
+                clamp_depth: false,
+                // Requires Features::CONSERVATIVE_RASTERIZATION
+                conservative: false,
+            },
+            depth_stencil: None, // 1.
+            multisample: wgpu::MultisampleState {
+                count: 1, // 2.
+                mask: !0, // 3.
+                alpha_to_coverage_enabled: false, // 4.
+            },
+        });
+
+        let vertex_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Vertex Buffer"),
+                //contents: bytemuck::cast_slice(&[]),
+                contents: &[],
+                usage: wgpu::BufferUsages::VERTEX,
+            }
+        );
+
+        let index_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Index Buffer"),
+                //contents: bytemuck::cast_slice(INDICES),
+                contents: &[],
+                usage: wgpu::BufferUsages::INDEX,
+            }
+        );
+
+        Self {
+            render_pipeline,
+            vertex_buffer,
+            num_indices: 0,
+            index_buffer,
+            scene: Scene::new(inputs, config),
+        }
+    }
+
+    pub fn scene(&self) -> &Scene {
+        &self.scene
+    }
+
+    pub fn scene_mut(&mut self) -> &mut Scene {
+        &mut self.scene
+    }
+
+    pub fn input(&mut self, action: Action) {
+        self.scene.handle_action(action);
+    }
+
+    /// returns true while something on screen changes without new audio data
+    pub fn animating(&self) -> bool {
+        self.scene.animating()
+    }
+
+    /// processes the raw spectrum of the current frame and rebuilds the mesh
+    /// for a render target of `size` physical pixels
+    pub fn update(&mut self, device: &wgpu::Device, spectrum: &Spectrum, size: [u32; 2]) {
+        self.scene.update(spectrum);
+
+        let (mut vertices, mut indices) = self.scene.mesh(
+            [size[0] as f32 * 0.001, size[1] as f32 * 0.001],
+        );
+        let pixel_size = [
+            2.0 * TEXT_SCALE / size[0] as f32,
+            2.0 * TEXT_SCALE / size[1] as f32,
+        ];
+        self.scene.overlay(pixel_size, &mut vertices, &mut indices);
+
+        self.num_indices = indices.len() as u32;
+
+        let vertex_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Vertex Buffer"),
+                contents: bytemuck::cast_slice(&vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            }
+        );
+        let index_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Index Buffer"),
+                contents: bytemuck::cast_slice(&indices),
+                usage: wgpu::BufferUsages::INDEX,
+            }
+        );
+
+        self.vertex_buffer = vertex_buffer;
+        self.index_buffer = index_buffer;
+    }
+
+    /// draws the visualisation into a render pass of the caller, without clearing it
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32); // 1.
+        render_pass.draw_indexed(0..self.num_indices, 0, 0..1); // 2.
+    }
+
+    /// clears `view` with the background of the theme and draws the visualisation into it
+    pub fn render(&self, device: &wgpu::Device, queue: &wgpu::Queue, view: &wgpu::TextureView) {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Render Encoder"),
+        });
+        let background = self.scene.background();
+
+        {
+            // 1.
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Render Pass"),
+                color_attachments: &[
+                    // This is what [[location(0)]] in the fragment shader targets
+                    wgpu::RenderPassColorAttachment {
+                        view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(
+                                wgpu::Color {
+                                    r: background[0] as f64,
+                                    g: background[1] as f64,
+                                    b: background[2] as f64,
+                                    a: 0.0,
+                                }
+                            ),
+                            store: true,
+                        }
+                    }
+                ],
+                depth_stencil_attachment: None,
+            });
+        
+            self.draw(&mut render_pass);
+        }
+
+        // submit will accept anything that implements IntoIter
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+}
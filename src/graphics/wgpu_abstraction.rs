@@ -1,51 +1,30 @@
 use winit::window::Window;
-use wgpu::util::DeviceExt;
-use std::sync::mpsc;
-use crate::config::Visualisation;
-
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct Vertex {
-    pub position: [f32; 3],
-    pub color: [f32; 3],
-}
-impl Vertex {
-    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    offset: 0,
-                    shader_location: 0,
-                    format: wgpu::VertexFormat::Float32x3,
-                },
-                wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
-                    shader_location: 1,
-                    format: wgpu::VertexFormat::Float32x3,
-                }
-            ]
-        }
-    }
-}
-
+use crate::config::{Action, Config};
+use crate::graphics::scene::Inputs;
+use crate::graphics::capture::{self, Capture};
+use crate::graphics::visualiser::Visualiser;
+use crate::renderer::{Renderer, Spectrum};
+use colored::*;
+
+/// renders the visualisation into a window
 pub struct State {
     surface: wgpu::Surface,
     device: wgpu::Device,
     queue: wgpu::Queue,
-    config: wgpu::SurfaceConfiguration,
+    surface_config: wgpu::SurfaceConfiguration,
     pub size: winit::dpi::PhysicalSize<u32>,
-    render_pipeline: wgpu::RenderPipeline,
-    vertex_buffer: wgpu::Buffer,
-    num_indices: u32,
-    index_buffer: wgpu::Buffer,
-    event_sender: mpsc::Sender<audioviz::Event>,
+    visualiser: Visualiser,
+    capture: Capture,
+    screenshot_requested: bool,
 }
 
 impl State {
     // Creating some of the wgpu types requires async code
-    pub async fn new(window: &Window, event_sender: mpsc::Sender<audioviz::Event>) -> Self {
+    pub async fn new(
+        window: &Window,
+        inputs: Inputs,
+        config: Config,
+    ) -> Self {
         let size = window.inner_size();
 
         // The instance is a handle to our GPU
@@ -59,6 +38,13 @@ impl State {
             },
         ).await.unwrap();
 
+        let adapter_info = adapter.get_info();
+        println!(
+            "using adapter: {} ({:?})",
+            adapter_info.name.green().bold(),
+            adapter_info.backend,
+        );
+
         let (device, queue) = adapter.request_device(
             &wgpu::DeviceDescriptor {
                 features: wgpu::Features::empty(),
@@ -68,199 +54,121 @@ impl State {
             None, // Trace path
         ).await.unwrap();
 
-        let config = wgpu::SurfaceConfiguration {
+        let surface_config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format: surface.get_preferred_format(&adapter).unwrap(),
             width: size.width,
             height: size.height,
-            present_mode: wgpu::PresentMode::Fifo,
+            present_mode: config.present_mode.to_wgpu(),
         };
-        surface.configure(&device, &config);
-
-        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
-            label: Some("Shader"),
-            //flags: wgpu::ShaderFlags::all(), // very weird behavior, could be the fault of rust nightly but I am not sure
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-        });
-
-        let render_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[],
-                push_constant_ranges: &[],
-            });
-
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "main", // 1.
-                buffers: &[
-                    Vertex::desc(),
-                ],
-            },
-            fragment: Some(wgpu::FragmentState { // 3.
-                module: &shader,
-                entry_point: "main",
-                targets: &[wgpu::ColorTargetState { // 4.
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                }],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList, // 1.
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw, // 2.
-                cull_mode: Some(wgpu::Face::Back),
-                // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-                polygon_mode: wgpu::PolygonMode::Fill,
-                // Requires Features::DEPTH_CLAMPING
-                clamp_depth: false,
-                // Requires Features::CONSERVATIVE_RASTERIZATION
-                conservative: false,
-            },
-            depth_stencil: None, // 1.
-            multisample: wgpu::MultisampleState {
-                count: 1, // 2.
-                mask: !0, // 3.
-                alpha_to_coverage_enabled: false, // 4.
-            },
-        });
-
-        let vertex_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Vertex Buffer"),
-                //contents: bytemuck::cast_slice(&[]),
-                contents: &[],
-                usage: wgpu::BufferUsages::VERTEX,
-            }
+        // wgpu 0.10 can not be asked for the supported present modes,
+        // it logs a warning and uses Fifo if the requested one is not supported
+        println!(
+            "requested present mode: {}{}",
+            format!("{:?}", config.present_mode).green().bold(),
+            if config.present_mode.to_wgpu() == wgpu::PresentMode::Fifo { "" } else { ", falls back to Fifo if unsupported" },
         );
+        surface.configure(&device, &surface_config);
 
-        let index_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Index Buffer"),
-                //contents: bytemuck::cast_slice(INDICES),
-                contents: &[],
-                usage: wgpu::BufferUsages::INDEX,
-            }
-        );
-        
+        let capture = Capture::new(config.capture.clone());
+        let visualiser = Visualiser::new(&device, surface_config.format, inputs, config);
 
         Self {
             surface,
             device,
             queue,
-            config,
+            surface_config,
             size,
-            render_pipeline,
-            vertex_buffer,
-            num_indices: 0,
-            index_buffer,
-            event_sender,
+            visualiser,
+            capture,
+            screenshot_requested: false,
         }
     }
 
-    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
-        if new_size.width > 0 && new_size.height > 0 {
-            self.size = new_size;
-            self.config.width = new_size.width;
-            self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
+    // copies the current frame back to save it as screenshot or add it to the recording
+    fn capture(&mut self) {
+        let frame = match capture::read_frame(
+            &self.device,
+            &self.queue,
+            &self.visualiser,
+            self.surface_config.format,
+            [self.size.width, self.size.height],
+        ) {
+            Ok(frame) => frame,
+            Err(e) => {
+                self.screenshot_requested = false;
+                self.capture.stop_recording();
+                self.visualiser.scene_mut().notify(e);
+                return;
+            }
+        };
+
+        if self.screenshot_requested {
+            self.screenshot_requested = false;
+            let notification = match self.capture.screenshot(&frame) {
+                Ok(path) => format!("saved {}", path.display()),
+                Err(e) => e,
+            };
+            self.visualiser.scene_mut().notify(notification);
+        }
+        if self.capture.recording() {
+            self.capture.record(frame);
         }
     }
 
-    pub fn input(&mut self, _event: &winit::event::WindowEvent) -> bool {
-        false
+    fn toggle_recording(&mut self) {
+        let notification = if self.capture.recording() {
+            self.capture.stop_recording();
+            String::from("recording stopped")
+        } else {
+            match self.capture.start_recording() {
+                Ok(path) => format!("recording to {}", path.display()),
+                Err(e) => e,
+            }
+        };
+        self.visualiser.scene_mut().notify(notification);
     }
+}
 
-    pub fn update(&mut self) {
-        let (tx, rx) = mpsc::channel();
-        self.event_sender.send(audioviz::Event::RequestData(tx)).unwrap();
-        let mut buffer = rx.recv().unwrap();
-
-        //buffer.drain((buffer.len() - 50)..buffer.len());
-        //buffer.drain(0..10);
-
-        for i in 0..buffer.len() {
-            buffer.insert(0, buffer[i*2]);
-        }
-
-        let (vertices, indices) = crate::graphics::mesh::from_buffer(
-            buffer,
-            Visualisation::Circle,
-            1.0,
-            1.0,
-            1.0,
-            [1.0, 0.0, 0.0],
-            [0.0, 0.0, 0.05],
-            [self.size.width as f32 * 0.001, self.size.height as f32 * 0.001],
-        );
-
-        self.num_indices = indices.len() as u32;
+impl Renderer for State {
+    type Error = wgpu::SurfaceError;
 
-        let vertex_buffer = self.device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Vertex Buffer"),
-                contents: bytemuck::cast_slice(&vertices),
-                usage: wgpu::BufferUsages::VERTEX,
-            }
-        );
-        let index_buffer = self.device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Index Buffer"),
-                contents: bytemuck::cast_slice(&indices),
-                usage: wgpu::BufferUsages::INDEX,
-            }
-        );
-
-        self.vertex_buffer = vertex_buffer;
-        self.index_buffer = index_buffer;
+    fn update(&mut self, spectrum: &Spectrum) {
+        self.visualiser.update(&self.device, spectrum, [self.size.width, self.size.height]);
     }
 
-    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+    fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
         let output = self.surface.get_current_frame()?.output;
         let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Render Encoder"),
-        });
+        self.visualiser.render(&self.device, &self.queue, &view);
 
-        {
-            // 1.
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[
-                    // This is what [[location(0)]] in the fragment shader targets
-                    wgpu::RenderPassColorAttachment {
-                        view: &view,
-                        resolve_target: None,
-                        ops: wgpu::Operations {
-                            load: wgpu::LoadOp::Clear(
-                                wgpu::Color {
-                                    r: 0.0,
-                                    g: 0.0,
-                                    b: 0.0,
-                                    a: 0.0,
-                                }
-                            ),
-                            store: true,
-                        }
-                    }
-                ],
-                depth_stencil_attachment: None,
-            });
-        
-            // NEW!
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32); // 1.
-            render_pass.draw_indexed(0..self.num_indices, 0, 0..1); // 2.
+        if self.screenshot_requested || self.capture.recording() {
+            self.capture();
         }
 
-        // submit will accept anything that implements IntoIter
-        self.queue.submit(std::iter::once(encoder.finish()));
-
         Ok(())
     }
-}
\ No newline at end of file
+
+    fn resize(&mut self, width: u32, height: u32) {
+        if width > 0 && height > 0 {
+            self.size = winit::dpi::PhysicalSize::new(width, height);
+            self.surface_config.width = width;
+            self.surface_config.height = height;
+            self.surface.configure(&self.device, &self.surface_config);
+        }
+    }
+
+    fn input(&mut self, action: Action) -> bool {
+        match action {
+            // saved on the next rendered frame
+            Action::Screenshot => self.screenshot_requested = true,
+            Action::ToggleRecording => self.toggle_recording(),
+            action => self.visualiser.input(action),
+        }
+        true
+    }
+
+    fn animating(&self) -> bool {
+        self.visualiser.animating()
+    }
+}
@@ -0,0 +1,91 @@
+use std::sync::Mutex;
+use winit::{
+    event::*,
+    event_loop::{ControlFlow, EventLoop},
+    window::WindowBuilder,
+};
+use crate::audio::AudioSource;
+use crate::config::Config;
+use crate::graphics::wgpu_abstraction::State;
+use crate::pacing::FramePacer;
+use crate::renderer::{self, Renderer, Source};
+
+/// opens a window and renders the visualisation into it until it gets closed
+pub fn run(audio: &AudioSource, mut source: Source, config: Config) -> ! {
+    let event_loop = EventLoop::new();
+    let proxy = Mutex::new(event_loop.create_proxy());
+    audio.activity.set_waker(Box::new(move || {
+        proxy.lock().unwrap().send_event(()).ok();
+    }));
+
+    let window = WindowBuilder::new().build(&event_loop).unwrap();
+    let mut frame_pacer = FramePacer::new(config.fps);
+    let keys = config.keys;
+    let activity = audio.activity.clone();
+    let mut state = pollster::block_on(State::new(&window, audio.inputs(), config));
+
+    event_loop.run(move |event, _, control_flow| {
+        match event {
+            Event::WindowEvent {
+                ref event,
+                window_id,
+            } if window_id == window.id() => {
+                match event {
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(key),
+                                ..
+                            },
+                        ..
+                    } if keys.action(*key).is_some_and(|action| state.input(action)) => {}
+                    WindowEvent::CloseRequested
+                    | WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::Escape),
+                                ..
+                            },
+                        ..
+                    } => *control_flow = ControlFlow::Exit,
+                    WindowEvent::Resized(physical_size) => {
+                        state.resize(physical_size.width, physical_size.height);
+                    }
+                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                        state.resize(new_inner_size.width, new_inner_size.height);
+                    }
+                    _ => {}
+                }
+            }
+            Event::RedrawRequested(_) => {
+                match renderer::draw(&mut state, &mut source) {
+                    Ok(_) => {}
+                    // Reconfigure the surface if lost
+                    Err(wgpu::SurfaceError::Lost) => state.resize(state.size.width, state.size.height),
+                    // The system is out of memory, we should probably quit
+                    Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
+                    // All other errors (Outdated, Timeout) should be resolved by the next frame
+                    Err(e) => eprintln!("{:?}", e),
+                }
+            }
+            Event::MainEventsCleared => {
+                // stops rendering on silence, the audio thread wakes the loop
+                // up again via a user event once sound is captured
+                if renderer::can_sleep(&state, &activity) {
+                    *control_flow = ControlFlow::Wait;
+                } else {
+                    let (redraw, flow) = frame_pacer.poll();
+                    *control_flow = flow;
+                    // RedrawRequested will only trigger once, unless we manually
+                    // request it.
+                    if redraw {
+                        window.request_redraw();
+                    }
+                }
+            }
+            _ => {}
+        }
+    });
+}
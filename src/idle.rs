@@ -0,0 +1,196 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use crate::config::Idle;
+
+/// keeps track of when sound was captured the last time,
+/// updated by the analysis thread and read by the render loop
+pub struct Activity {
+    enabled: bool,
+    threshold: f32,
+    timeout: Duration,
+    start: Instant,
+    // milliseconds since `start`
+    last_sound: AtomicU64,
+    sleeping: AtomicBool,
+    // milliseconds since `start` of the last captured samples, silent or not
+    last_capture: AtomicU64,
+    connected: AtomicBool,
+    // bits of the peak and rms level of the last captured samples
+    peak: AtomicU32,
+    rms: AtomicU32,
+    waker: Mutex<Option<Box<dyn Fn() + Send>>>,
+}
+
+impl Activity {
+    pub fn new(config: Idle) -> Self {
+        Self {
+            enabled: config.enabled,
+            threshold: config.threshold,
+            timeout: Duration::from_secs_f32(config.timeout.max(0.0)),
+            start: Instant::now(),
+            last_sound: AtomicU64::new(0),
+            sleeping: AtomicBool::new(false),
+            last_capture: AtomicU64::new(0),
+            connected: AtomicBool::new(true),
+            peak: AtomicU32::new(0),
+            rms: AtomicU32::new(0),
+            waker: Mutex::new(None),
+        }
+    }
+
+    /// sets the function that wakes up the render loop once sound is captured again
+    pub fn set_waker(&self, waker: Box<dyn Fn() + Send>) {
+        *self.waker.lock().unwrap() = Some(waker);
+    }
+
+    /// registers captured samples,
+    /// returns false if they do not need to be processed because of silence
+    pub fn process(&self, samples: &[f32]) -> bool {
+        let peak = samples.iter().fold(0.0_f32, |peak, sample| peak.max(sample.abs()));
+        let rms = if samples.is_empty() {
+            0.0
+        } else {
+            (samples.iter().map(|sample| sample * sample).sum::<f32>() / samples.len() as f32).sqrt()
+        };
+        self.peak.store(peak.to_bits(), Ordering::Relaxed);
+        self.rms.store(rms.to_bits(), Ordering::Relaxed);
+        self.last_capture.store(self.elapsed(), Ordering::Relaxed);
+
+        if peak > self.threshold {
+            self.wake();
+            return true;
+        }
+
+        !self.is_idle()
+    }
+
+    /// sets whether the audio device is connected,
+    /// wakes the render loop up so that the change gets shown
+    pub fn set_connected(&self, connected: bool) {
+        self.connected.store(connected, Ordering::SeqCst);
+        self.last_capture.store(self.elapsed(), Ordering::Relaxed);
+        self.wake();
+    }
+
+    pub fn connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+
+    /// time since samples were captured the last time, silent or not
+    pub fn since_last_capture(&self) -> Duration {
+        Duration::from_millis(self.elapsed().saturating_sub(self.last_capture.load(Ordering::Relaxed)))
+    }
+
+    /// highest sample level of the last captured samples
+    pub fn peak(&self) -> f32 {
+        f32::from_bits(self.peak.load(Ordering::Relaxed))
+    }
+
+    /// rms level of the last captured samples
+    pub fn rms(&self) -> f32 {
+        f32::from_bits(self.rms.load(Ordering::Relaxed))
+    }
+
+    pub fn is_idle(&self) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        let silence = self.elapsed().saturating_sub(self.last_sound.load(Ordering::SeqCst));
+        silence > self.timeout.as_millis() as u64
+    }
+
+    /// returns true if the render loop is allowed to sleep until it gets woken up
+    pub fn try_sleep(&self) -> bool {
+        if !self.is_idle() {
+            return false;
+        }
+        self.sleeping.store(true, Ordering::SeqCst);
+
+        // sound could have been captured between the check and going to sleep
+        if !self.is_idle() {
+            self.sleeping.store(false, Ordering::SeqCst);
+            return false;
+        }
+        true
+    }
+
+    // counts as sound, so the render loop keeps running for the timeout
+    fn wake(&self) {
+        self.last_sound.store(self.elapsed(), Ordering::SeqCst);
+        if self.sleeping.swap(false, Ordering::SeqCst) {
+            if let Some(waker) = self.waker.lock().unwrap().as_ref() {
+                waker();
+            }
+        }
+    }
+
+    fn elapsed(&self) -> u64 {
+        self.start.elapsed().as_millis() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+    use std::thread;
+
+    const TIMEOUT: Duration = Duration::from_millis(50);
+
+    fn activity(enabled: bool) -> Activity {
+        Activity::new(Idle {
+            enabled,
+            threshold: 0.1,
+            timeout: TIMEOUT.as_secs_f32(),
+        })
+    }
+
+    #[test]
+    fn idle_after_the_timeout() {
+        let activity = activity(true);
+        assert!(activity.process(&[0.5, -0.2]));
+        assert!(!activity.is_idle());
+
+        // quiet samples are still processed until the timeout
+        assert!(activity.process(&[0.05, -0.05]));
+        assert!(!activity.try_sleep());
+
+        thread::sleep(TIMEOUT + Duration::from_millis(20));
+        assert!(!activity.process(&[0.05, -0.05]));
+        assert!(activity.is_idle());
+        assert!(activity.try_sleep());
+        // the levels are kept up to date even while idle
+        assert_eq!(activity.peak(), 0.05);
+    }
+
+    #[test]
+    fn first_loud_frame_wakes_up() {
+        let activity = activity(true);
+        let wakes = Arc::new(AtomicUsize::new(0));
+        let counter = wakes.clone();
+        activity.set_waker(Box::new(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        }));
+
+        thread::sleep(TIMEOUT + Duration::from_millis(20));
+        assert!(activity.try_sleep());
+        assert!(activity.process(&[0.0, -0.5]));
+        assert!(!activity.is_idle());
+        assert_eq!(wakes.load(Ordering::SeqCst), 1);
+
+        // only a sleeping loop gets woken up
+        assert!(activity.process(&[0.5]));
+        assert_eq!(wakes.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn disabled_is_never_idle() {
+        let activity = activity(false);
+        thread::sleep(TIMEOUT + Duration::from_millis(20));
+        assert!(activity.process(&[0.0]));
+        assert!(!activity.is_idle());
+        assert!(!activity.try_sleep());
+    }
+}
@@ -0,0 +1,42 @@
+//! audio visualiser, usable as application or embedded into other winit/wgpu applications
+//!
+//! `AudioSource` captures and analyses the audio, `Visualiser` renders it with wgpu
+//! into a render pass or texture view of the caller, the `mesh` of every visualisation
+//! can also be generated on its own
+
+pub mod graphics;
+pub mod config;
+pub mod audio;
+pub mod idle;
+pub mod beat;
+pub mod renderer;
+pub mod pitch;
+pub mod meter;
+pub mod server;
+pub mod monitor;
+
+// the ring buffer is no api of its own, only public for `cargo bench --features bench`
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod ring;
+#[cfg(not(feature = "bench"))]
+mod ring;
+
+mod terminal;
+mod osc;
+mod amplitude;
+mod agc;
+mod pacing;
+mod triple;
+mod analysis;
+mod notes;
+mod published;
+
+pub use config::{Config, Visualisation};
+pub use audio::{AudioDevice, AudioSource};
+pub use graphics::mesh;
+pub use graphics::scene::Inputs;
+pub use graphics::visualiser::Visualiser;
+pub use renderer::{Renderer, Source};
+pub use terminal::run as run_terminal;
+pub use osc::init_osc_sender;
@@ -1,18 +1,11 @@
-use winit::{
-    event::*,
-    event_loop::{ControlFlow, EventLoop},
-    window::WindowBuilder,
-};
-use audioviz;
-
-mod graphics;
-use graphics::wgpu_abstraction::State;
-mod config;
-pub use config::Visualisation;
-mod audio;
-use audio::*;
+use audiovis::{audio, graphics, monitor, renderer};
+use audiovis::audio::{AudioDevice, AudioSource};
+use audiovis::config::{self, Config};
+use audiovis::renderer::Source;
+use audiovis::server::Server;
 
 use clap::{Arg, App, AppSettings};
+use colored::*;
 
 fn main() {
     let matches = App::new("audiovis")
@@ -43,6 +36,43 @@ fn main() {
                 .takes_value(true)
                 .help("use specific output device"))
 
+    .arg(Arg::with_name("monitor")
+                .short("m")
+                .long("monitor")
+                .takes_value(false)
+                .conflicts_with_all(&["input_device", "output_device"])
+                .help("captures what the system plays via the monitor of the default sink, linux only"))
+
+    .arg(Arg::with_name("serve")
+                .long("serve")
+                .takes_value(true)
+                .value_name("ADDRESS")
+                .help("publishes spectrum, peak level and beats to tcp clients, e.g. 127.0.0.1:7777"))
+
+    .arg(Arg::with_name("serve_format")
+                .long("serve-format")
+                .takes_value(true)
+                .possible_values(&["json", "binary"])
+                .default_value("json")
+                .help("format of the published frames"))
+
+    .arg(Arg::with_name("headless")
+                .long("headless")
+                .takes_value(false)
+                .help("runs without window, only publishing via --serve and osc"))
+
+    .arg(Arg::with_name("terminal")
+                .long("terminal")
+                .takes_value(false)
+                .help("renders to the terminal instead of a window"))
+
+    .arg(Arg::with_name("offscreen")
+                .long("offscreen")
+                .takes_value(true)
+                .value_name("WIDTHxHEIGHT")
+                .conflicts_with_all(&["headless", "terminal"])
+                .help("renders without window into a texture, recording every frame to the capture directory"))
+
     .arg(Arg::with_name("generate_default_config")
                 .short("g")
                 .long("generate-default-config")
@@ -52,124 +82,135 @@ fn main() {
     .get_matches();
 
     let audio_device: AudioDevice = 
-        //if matches.value_of("input_device").unwrap_or("0").parse().unwrap();
         if matches.is_present("input_device") {
-            AudioDevice::Input(matches.value_of("input_device").unwrap_or("0").parse().unwrap())
+            AudioDevice::Input(parse_device_index(matches.value_of("input_device")))
         }
         else if matches.is_present("output_device") {
-            AudioDevice::Output(matches.value_of("output_device").unwrap_or("0").parse().unwrap())
+            AudioDevice::Output(parse_device_index(matches.value_of("output_device")))
+        }
+        else if matches.is_present("monitor") {
+            AudioDevice::Monitor
         } else {
             AudioDevice::Output(0)
         };
 
     if matches.is_present("iter_devices") {
-        iter_audio_devices();
+        if let Err(e) = audio::iter_audio_devices() {
+            exit_with_audio_error(e);
+        }
         std::process::exit(0);
     }
 
-    let audio_stream = audioviz::AudioStream::init(
-        audioviz::Config {
-            density_reduction: 0,
-            smoothing_size: 5,
-            smoothing_amount: 10,
-            frequency_scale_range: [0, 1000],
-            frequency_scale_amount: 0,
-            max_frequency: 20_000,
-            buffering: 5,
-            resolution: 3000,
-            volume: 0.5,
-            ..Default::default()
+    if matches.is_present("generate_default_config") {
+        if let Err(e) = std::fs::write("default.config.toml", config::DEFAULT_CONFIG) {
+            eprintln!("{} {}", "failed to generate default config:".red().bold(), e);
+            std::process::exit(1);
         }
-    );
-    let event_sender = audio_stream.get_event_sender();
-
-    init_audio_sender(event_sender.clone(), audio_device);
-    //init_auto_volume(event_sender.clone());
-
-    let event_loop = EventLoop::new();
-    let window = WindowBuilder::new().build(&event_loop).unwrap();
-    let mut state = pollster::block_on(State::new(&window, event_sender));
-
-    event_loop.run(move |event, _, control_flow| {
-        match event {
-            Event::WindowEvent {
-                ref event,
-                window_id,
-            } if window_id == window.id() => if !state.input(event) { // UPDATED!
-                match event {
-                    WindowEvent::CloseRequested
-                    | WindowEvent::KeyboardInput {
-                        input:
-                            KeyboardInput {
-                                state: ElementState::Pressed,
-                                virtual_keycode: Some(VirtualKeyCode::Escape),
-                                ..
-                            },
-                        ..
-                    } => *control_flow = ControlFlow::Exit,
-                    WindowEvent::Resized(physical_size) => {
-                        state.resize(*physical_size);
-                    }
-                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                        state.resize(**new_inner_size);
-                    }
-                    _ => {}
-                }
+        println!("generated {}", "default.config.toml".green().bold());
+        std::process::exit(0);
+    }
+
+    let config: Config = match matches.value_of("config") {
+        Some(path) => match Config::load(path) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("{} {}", "invalid configuration:".red().bold(), e);
+                std::process::exit(1);
             }
-            winit::event::Event::RedrawRequested(_) => {
-                state.update();
-                match state.render() {
-                    Ok(_) => {}
-                    // Reconfigure the surface if lost
-                    Err(wgpu::SurfaceError::Lost) => state.resize(state.size),
-                    // The system is out of memory, we should probably quit
-                    Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
-                    // All other errors (Outdated, Timeout) should be resolved by the next frame
-                    Err(e) => eprintln!("{:?}", e),
-                }
+        },
+        None => Config::default(),
+    };
+
+    // changes the environment, so it has to happen before any threads get spawned
+    if audio_device == AudioDevice::Monitor {
+        if let Err(e) = monitor::select_source() {
+            exit_with_audio_error(e);
+        }
+    }
+
+    let audio = match AudioSource::new(audio_device, &config) {
+        Ok(audio) => audio,
+        Err(e) => exit_with_audio_error(e),
+    };
+
+    let server = matches.value_of("serve").map(|address| {
+        let format = matches.value_of("serve_format").unwrap_or("json").parse().unwrap();
+        match Server::bind(address, format, audio.beat_events.subscribe(), audio.activity.clone()) {
+            Ok(server) => {
+                println!("serving on: {}", address.green().bold());
+                server
             }
-            winit::event::Event::MainEventsCleared => {
-                // RedrawRequested will only trigger once, unless we manually
-                // request it.
-                window.request_redraw();
+            Err(e) => {
+                eprintln!("{} {}: {}", "failed to serve on".red().bold(), address, e);
+                std::process::exit(1);
             }
-            _ => {}
         }
     });
-}
-/*
-fn init_auto_volume(event_sender: mpsc::Sender<audioviz::Event>) {
-    thread::spawn(move || loop {
-        let (tx, rx) = mpsc::channel();
-        event_sender.send(audioviz::Event::RequestConfig(tx)).unwrap();
-        let config = rx.recv().unwrap();
-
-        let (tx, rx) = mpsc::channel();
-        event_sender.send(audioviz::Event::RequestData(tx)).unwrap();
-        let data = rx.recv().unwrap();
-
-        let mut average: f32 = 0.0;
-        for i in data.iter() {
-            if *i > average {
-                average = *i;
+
+    if config.osc.enabled {
+        match audiovis::init_osc_sender(
+            config.osc.clone(),
+            audio.event_sender(),
+            audio.activity.clone(),
+            audio.beat_events.subscribe(),
+        ) {
+            Ok(_) => println!("sending osc to: {}", config.osc.target.green().bold()),
+            Err(e) => {
+                eprintln!("{} {}: {}", "failed to send osc to".red().bold(), config.osc.target, e);
+                std::process::exit(1);
             }
         }
+    }
 
-        let wanted_volume_amplitude = if average > 0.25 {
-            config.volume - 0.01
-        } else {
-            config.volume + 0.01
-        };
+    let source = Source::new(audio.event_sender(), audio.activity.clone(), config.audio.refresh_rate, server);
 
-        let wanted_config: audioviz::Config = audioviz::Config {
-            volume: wanted_volume_amplitude,
-            ..config
-        };
+    if matches.is_present("headless") {
+        renderer::run(renderer::Headless, source, &audio.activity, config.fps);
+    }
 
-        event_sender.send(audioviz::Event::SendConfig(wanted_config)).unwrap();
+    if matches.is_present("terminal") {
+        audiovis::run_terminal(&audio, source, config);
+    }
 
-        thread::sleep(std::time::Duration::from_millis(50));
-    });
+    if let Some(size) = matches.value_of("offscreen") {
+        let size = parse_size(size);
+        let mut offscreen = pollster::block_on(graphics::offscreen::Offscreen::new(audio.inputs(), config.clone(), size))
+            .unwrap_or_else(|e| exit_with_error("offscreen error:", e));
+        match offscreen.start_recording() {
+            Ok(path) => println!("recording to {}", path.display().to_string().green().bold()),
+            Err(e) => exit_with_error("offscreen error:", e),
+        }
+        renderer::run(offscreen, source, &audio.activity, config.fps);
+    }
+
+    graphics::window::run(&audio, source, config);
 }
-*/
 
+fn parse_size(size: &str) -> [u32; 2] {
+    let parsed = size.split_once('x').and_then(|(width, height)| Some([width.parse().ok()?, height.parse().ok()?]));
+    match parsed {
+        Some([width, height]) if width > 0 && height > 0 => [width, height],
+        _ => exit_with_error("invalid size:", format!("{}, expected e.g. 1280x720", size)),
+    }
+}
+
+fn exit_with_error(kind: &str, e: String) -> ! {
+    eprintln!("{} {}", kind.red().bold(), e);
+    std::process::exit(1);
+}
+
+fn parse_device_index(index: Option<&str>) -> usize {
+    let index = index.unwrap_or("0");
+    match index.parse() {
+        Ok(index) => index,
+        Err(_) => {
+            eprintln!("{} {}, expected the number of a device, see --iter-devices", "invalid device:".red().bold(), index);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn exit_with_audio_error(e: audio::AudioError) -> ! {
+    eprintln!("{} {}", "audio error:".red().bold(), e);
+    std::process::exit(1);
+}
@@ -0,0 +1,485 @@
+use std::collections::VecDeque;
+use crate::config;
+use crate::published::Published;
+
+// duration of the blocks the levels get measured in, also how often they get published
+const BLOCK_DURATION: f32 = 0.05;
+// blocks of the rms window (300 ms), the momentary loudness (400 ms) and the short-term loudness (3 s)
+const RMS_BLOCKS: usize = 6;
+const MOMENTARY_BLOCKS: usize = 8;
+const SHORT_TERM_BLOCKS: usize = 60;
+// gating blocks of the integrated loudness overlap by 75%, so a new one starts every 100 ms
+const GATING_STEP: usize = 2;
+// gates of the integrated loudness after EBU R128
+const ABSOLUTE_GATE: f32 = -70.0;
+const RELATIVE_GATE: f32 = -10.0;
+// gating blocks get counted in bins of 0.1 LU from the absolute gate up to +5 LUFS
+const GATING_BIN: f32 = 0.1;
+const GATING_BINS: usize = 750;
+/// lowest level in dB, silence is reported as this instead of negative infinity
+pub const MIN_DB: f32 = -120.0;
+
+// true peaks get searched at four times the sample rate, with a windowed sinc of 12 taps per phase
+const OVERSAMPLING: usize = 4;
+const TAPS: usize = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelLevels {
+    // falling peak in dBFS
+    pub peak: f32,
+    // rms of the last 300 ms in dBFS
+    pub rms: f32,
+    // highest recent peak in dBFS, held for a while
+    pub hold: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Levels {
+    pub channels: Vec<ChannelLevels>,
+    // loudness after EBU R128 in LUFS, None until enough samples were measured
+    pub momentary: Option<f32>,
+    pub short_term: Option<f32>,
+    pub integrated: Option<f32>,
+    // highest true peak since the start in dBTP
+    pub true_peak: f32,
+}
+
+// biquad filter in direct form 2 transposed
+#[derive(Debug, Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+// both stages of the k-weighting of ITU-R BS.1770 for any sample rate,
+// a high shelf for the head and a high pass
+fn k_weighting(sample_rate: f64) -> [Biquad; 2] {
+    let k = (std::f64::consts::PI * 1681.974450955533 / sample_rate).tan();
+    let q = 0.7071752369554196;
+    let vh = 10.0_f64.powf(3.999843853973347 / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b: [(vh + vb * k / q + k * k) / a0, 2.0 * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        z: [0.0; 2],
+    };
+
+    let k = (std::f64::consts::PI * 38.13547087602444 / sample_rate).tan();
+    let q = 0.5003270373238773;
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad {
+        b: [1.0, -2.0, 1.0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        z: [0.0; 2],
+    };
+    [shelf, high_pass]
+}
+
+// weight of a channel in the loudness, the lfe of 5.1 is left out and its surrounds count more
+fn channel_weight(channel: usize, channels: usize) -> f64 {
+    match (channels, channel) {
+        (6, 3) => 0.0,
+        (6, 4) | (6, 5) => 1.41,
+        _ => 1.0,
+    }
+}
+
+// phases of the interpolation filter for the true peak, every phase sums up to 1.0
+fn interpolation_filter() -> [[f32; TAPS]; OVERSAMPLING] {
+    let length = OVERSAMPLING * TAPS;
+    let center = length as f32 / 2.0;
+    let mut phases = [[0.0; TAPS]; OVERSAMPLING];
+    for (phase, taps) in phases.iter_mut().enumerate() {
+        for (tap, value) in taps.iter_mut().enumerate() {
+            let x = (tap * OVERSAMPLING + phase) as f32 - center;
+            let sinc = if x == 0.0 {
+                1.0
+            } else {
+                let x = std::f32::consts::PI * x / OVERSAMPLING as f32;
+                x.sin() / x
+            };
+            let window = 0.5 + 0.5 * (std::f32::consts::PI * x / center).cos();
+            *value = sinc * window;
+        }
+        let sum: f32 = taps.iter().sum();
+        taps.iter_mut().for_each(|value| *value /= sum);
+    }
+    phases
+}
+
+fn to_db(amplitude: f32) -> f32 {
+    (20.0 * amplitude.log10()).max(MIN_DB)
+}
+
+fn loudness(energy: f64) -> f32 {
+    ((-0.691 + 10.0 * energy.log10()) as f32).max(MIN_DB)
+}
+
+// bin of the gating histogram of a loudness above the absolute gate
+fn gating_bin(loudness: f32) -> usize {
+    (((loudness - ABSOLUTE_GATE) / GATING_BIN) as usize).min(GATING_BINS - 1)
+}
+
+// state of a single channel
+#[derive(Debug, Clone)]
+struct Channel {
+    filter: [Biquad; 2],
+    weight: f64,
+    // newest samples first, for the interpolation of the true peak
+    history: [f32; TAPS],
+
+    // of the current block
+    peak: f32,
+    energy: f64,
+    weighted_energy: f64,
+    // mean squares of the last blocks for the rms
+    energies: VecDeque<f64>,
+
+    levels: ChannelLevels,
+    hold_age: f32,
+}
+
+/// peak, rms, loudness after EBU R128 and true peak meter that can be fed with any stream of f32 samples
+pub struct Meter {
+    sample_rate: u32,
+    config: config::Meters,
+    block_size: usize,
+    interpolation: [[f32; TAPS]; OVERSAMPLING],
+
+    channels: Vec<Channel>,
+    // interleaved samples of an incomplete frame
+    channel: usize,
+    // frames of the current block
+    frames: usize,
+
+    // weighted mean squares of the last blocks for the momentary and short-term loudness
+    blocks: VecDeque<f64>,
+    // sums and counts of the mean squares of the gating blocks above the absolute gate,
+    // in total and per bin of their loudness, for the integrated loudness
+    gated_energy: f64,
+    gated_count: u64,
+    bin_energies: Vec<f64>,
+    bin_counts: Vec<u64>,
+    blocks_since_gating: usize,
+    true_peak: f32,
+
+    levels: Levels,
+}
+
+impl Meter {
+    pub fn new(sample_rate: u32, channels: u16, config: config::Meters) -> Self {
+        let channel_count = channels.max(1) as usize;
+        let channel = Channel {
+            filter: k_weighting(sample_rate as f64),
+            weight: 1.0,
+            history: [0.0; TAPS],
+            peak: 0.0,
+            energy: 0.0,
+            weighted_energy: 0.0,
+            energies: VecDeque::with_capacity(RMS_BLOCKS),
+            levels: ChannelLevels { peak: MIN_DB, rms: MIN_DB, hold: MIN_DB },
+            hold_age: 0.0,
+        };
+
+        Self {
+            sample_rate,
+            config,
+            block_size: ((sample_rate as f32 * BLOCK_DURATION) as usize).max(1),
+            interpolation: interpolation_filter(),
+            channels: (0..channel_count)
+                .map(|i| Channel { weight: channel_weight(i, channel_count), ..channel.clone() })
+                .collect(),
+            channel: 0,
+            frames: 0,
+            blocks: VecDeque::with_capacity(SHORT_TERM_BLOCKS),
+            gated_energy: 0.0,
+            gated_count: 0,
+            bin_energies: vec![0.0; GATING_BINS],
+            bin_counts: vec![0; GATING_BINS],
+            blocks_since_gating: 0,
+            true_peak: 0.0,
+            levels: Levels::default(),
+        }
+    }
+
+    /// forgets everything measured so far, including the integrated loudness,
+    /// does nothing if nothing was measured since the last reset
+    pub fn reset(&mut self) {
+        if self.channel == 0 && self.frames == 0 && self.blocks.is_empty() {
+            return;
+        }
+        *self = Self::new(self.sample_rate, self.channels.len() as u16, self.config);
+    }
+
+    /// processes interleaved samples, returns true if new levels got measured
+    pub fn process(&mut self, samples: &[f32]) -> bool {
+        let mut measured = false;
+        for sample in samples {
+            let channel = &mut self.channels[self.channel];
+
+            channel.history.copy_within(0..TAPS - 1, 1);
+            channel.history[0] = *sample;
+            for phase in self.interpolation.iter() {
+                let interpolated: f32 = phase.iter().zip(channel.history.iter()).map(|(h, x)| h * x).sum();
+                self.true_peak = self.true_peak.max(interpolated.abs());
+            }
+
+            channel.peak = channel.peak.max(sample.abs());
+            channel.energy += (*sample as f64) * (*sample as f64);
+            let weighted = channel.filter.iter_mut().fold(*sample as f64, |x, stage| stage.process(x));
+            channel.weighted_energy += weighted * weighted;
+
+            self.channel += 1;
+            if self.channel < self.channels.len() {
+                continue;
+            }
+            self.channel = 0;
+            self.frames += 1;
+            if self.frames >= self.block_size {
+                self.finish_block();
+                measured = true;
+            }
+        }
+        measured
+    }
+
+    /// levels of the last block
+    pub fn levels(&self) -> &Levels {
+        &self.levels
+    }
+
+    fn finish_block(&mut self) {
+        let frames = self.frames as f64;
+        self.frames = 0;
+
+        let mut weighted_energy = 0.0;
+        for channel in self.channels.iter_mut() {
+            weighted_energy += channel.weight * channel.weighted_energy / frames;
+            if channel.energies.len() == RMS_BLOCKS {
+                channel.energies.pop_front();
+            }
+            channel.energies.push_back(channel.energy / frames);
+
+            // the peak falls at peak_decay, the hold only after it was held for a while
+            let levels = &mut channel.levels;
+            let peak = to_db(channel.peak);
+            levels.peak = peak.max(levels.peak - self.config.peak_decay * BLOCK_DURATION).max(MIN_DB);
+            channel.hold_age += BLOCK_DURATION;
+            if peak >= levels.hold || channel.hold_age > self.config.hold {
+                levels.hold = levels.peak;
+                channel.hold_age = 0.0;
+            }
+            let mean = channel.energies.iter().sum::<f64>() / channel.energies.len() as f64;
+            levels.rms = to_db(mean.sqrt() as f32);
+
+            channel.peak = 0.0;
+            channel.energy = 0.0;
+            channel.weighted_energy = 0.0;
+        }
+
+        if self.blocks.len() == SHORT_TERM_BLOCKS {
+            self.blocks.pop_front();
+        }
+        self.blocks.push_back(weighted_energy);
+        let mean_of_last = |count: usize| -> Option<f64> {
+            if self.blocks.len() < count {
+                return None;
+            }
+            Some(self.blocks.iter().rev().take(count).sum::<f64>() / count as f64)
+        };
+        let momentary = mean_of_last(MOMENTARY_BLOCKS);
+        let short_term = mean_of_last(SHORT_TERM_BLOCKS);
+
+        self.blocks_since_gating += 1;
+        if let Some(momentary) = momentary.filter(|_| self.blocks_since_gating >= GATING_STEP) {
+            self.blocks_since_gating = 0;
+            let level = loudness(momentary);
+            if level > ABSOLUTE_GATE {
+                let bin = gating_bin(level);
+                self.bin_energies[bin] += momentary;
+                self.bin_counts[bin] += 1;
+                self.gated_energy += momentary;
+                self.gated_count += 1;
+            }
+        }
+
+        self.levels.channels = self.channels.iter().map(|channel| channel.levels).collect();
+        self.levels.momentary = momentary.map(loudness);
+        self.levels.short_term = short_term.map(loudness);
+        self.levels.integrated = self.integrated();
+        self.levels.true_peak = to_db(self.true_peak);
+    }
+
+    // mean of the gating blocks that are at most 10 LU below the mean of all of them,
+    // the relative gate is rounded down to the bin it falls into
+    fn integrated(&self) -> Option<f32> {
+        if self.gated_count == 0 {
+            return None;
+        }
+        let gate = loudness(self.gated_energy / self.gated_count as f64) + RELATIVE_GATE;
+        let first = if gate > ABSOLUTE_GATE { gating_bin(gate) } else { 0 };
+        let sum: f64 = self.bin_energies[first..].iter().sum();
+        let count: u64 = self.bin_counts[first..].iter().sum();
+        if count == 0 {
+            return None;
+        }
+        Some(loudness(sum / count as f64))
+    }
+}
+
+/// latest levels, shared between the analysis thread and the renderers
+///
+/// the meters only measure while a renderer shows them
+pub type MeterState = Published<Levels>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_4;
+    use std::f64::consts::TAU;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    fn meter() -> Meter {
+        Meter::new(SAMPLE_RATE, 2, crate::Config::default().meters)
+    }
+
+    // stereo sine with the same signal on both channels
+    fn sine(frequency: f64, db: f32, phase: f32, seconds: f32) -> Vec<f32> {
+        let amplitude = 10.0_f64.powf(db as f64 / 20.0);
+        let frames = (seconds * SAMPLE_RATE as f32) as usize;
+        (0..frames)
+            .flat_map(|i| {
+                // in f64, the phase of long signals is too large for f32
+                let time = i as f64 / SAMPLE_RATE as f64;
+                let value = (amplitude * (TAU * frequency * time + phase as f64).sin()) as f32;
+                [value, value]
+            })
+            .collect()
+    }
+
+    fn silence(seconds: f32) -> Vec<f32> {
+        vec![0.0; (seconds * SAMPLE_RATE as f32) as usize * 2]
+    }
+
+    fn assert_near(value: f32, expected: f32, tolerance: f32) {
+        assert!((value - expected).abs() <= tolerance, "{} is not {}", value, expected);
+    }
+
+    #[test]
+    fn sine_at_minus_20_dbfs() {
+        let mut meter = meter();
+        assert!(meter.process(&sine(1000.0, -20.0, 0.0, 4.0)));
+        let levels = meter.levels();
+
+        assert_eq!(levels.channels.len(), 2);
+        for channel in levels.channels.iter() {
+            assert_near(channel.peak, -20.0, 0.01);
+            assert_near(channel.hold, -20.0, 0.01);
+            assert_near(channel.rms, -23.01, 0.01);
+        }
+        // a sine at 1 khz on both channels is as loud as its peak
+        assert_near(levels.momentary.unwrap(), -20.0, 0.1);
+        assert_near(levels.short_term.unwrap(), -20.0, 0.1);
+        assert_near(levels.integrated.unwrap(), -20.0, 0.1);
+        assert_near(levels.true_peak, -20.0, 0.05);
+    }
+
+    #[test]
+    fn loudness_needs_full_windows() {
+        let mut meter = meter();
+        meter.process(&sine(1000.0, -20.0, 0.0, 0.3));
+        assert_eq!(meter.levels().momentary, None);
+        assert_eq!(meter.levels().integrated, None);
+        meter.process(&sine(1000.0, -20.0, 0.0, 0.2));
+        assert!(meter.levels().momentary.is_some());
+        assert_eq!(meter.levels().short_term, None);
+    }
+
+    #[test]
+    fn absolute_gate() {
+        let mut meter = meter();
+        meter.process(&silence(2.0));
+        assert_eq!(meter.levels().momentary, Some(MIN_DB));
+        assert_eq!(meter.levels().integrated, None);
+
+        // measured, but below the gate of -70 LUFS
+        meter.process(&sine(1000.0, -80.0, 0.0, 2.0));
+        assert_near(meter.levels().momentary.unwrap(), -80.0, 0.1);
+        assert_eq!(meter.levels().integrated, None);
+    }
+
+    #[test]
+    fn relative_gate() {
+        let mut meter = meter();
+        meter.process(&sine(1000.0, -20.0, 0.0, 10.0));
+        // 20 LU below the loud part, without the relative gate the integrated loudness would be -23 LUFS
+        meter.process(&sine(1000.0, -40.0, 0.0, 10.0));
+        assert_near(meter.levels().momentary.unwrap(), -40.0, 0.1);
+        assert_near(meter.levels().integrated.unwrap(), -20.0, 0.2);
+
+        // within 10 LU everything counts
+        let mut meter = self::meter();
+        meter.process(&sine(1000.0, -20.0, 0.0, 10.0));
+        meter.process(&sine(1000.0, -26.0, 0.0, 10.0));
+        let expected = 10.0 * ((0.01 + 0.01 * 10.0_f32.powf(-0.6)) / 2.0).log10();
+        assert_near(meter.levels().integrated.unwrap(), expected, 0.2);
+    }
+
+    #[test]
+    fn peak_hold_and_decay() {
+        let config = crate::Config::default().meters;
+        let mut meter = meter();
+        meter.process(&sine(1000.0, -6.0, 0.0, BLOCK_DURATION));
+        assert_near(meter.levels().channels[0].peak, -6.0, 0.01);
+
+        // the peak falls at peak_decay while the hold stays
+        meter.process(&silence(1.0));
+        let levels = meter.levels().channels[0];
+        assert_near(levels.peak, -6.0 - config.peak_decay, 0.01);
+        assert_near(levels.hold, -6.0, 0.01);
+
+        // until it is held longer than hold, then it holds the falling peak
+        meter.process(&silence(config.hold - 1.0 + 2.0 * BLOCK_DURATION));
+        let levels = meter.levels().channels[0];
+        assert!(levels.hold < -40.0);
+        assert!(levels.hold > levels.peak && levels.hold - levels.peak <= config.peak_decay * BLOCK_DURATION + 0.01);
+        assert_eq!(meter.levels().channels[0].rms, MIN_DB);
+
+        // a new higher peak replaces the hold at once
+        meter.process(&sine(1000.0, -3.0, 0.0, BLOCK_DURATION));
+        assert_near(meter.levels().channels[0].hold, -3.0, 0.01);
+    }
+
+    #[test]
+    fn true_peak_between_samples() {
+        // at a quarter of the sample rate and shifted by 45 degrees every sample misses the peak by 3 dB
+        let mut meter = meter();
+        meter.process(&sine(SAMPLE_RATE as f64 / 4.0, -6.0, FRAC_PI_4, 1.0));
+        let levels = meter.levels();
+        assert_near(levels.channels[0].peak, -9.01, 0.01);
+        assert_near(levels.true_peak, -6.0, 0.3);
+    }
+
+    #[test]
+    fn reset_forgets_the_integrated_loudness() {
+        let mut meter = meter();
+        meter.process(&sine(1000.0, -20.0, 0.0, 2.0));
+        assert!(meter.levels().integrated.is_some());
+        meter.reset();
+        meter.process(&silence(2.0));
+        assert_eq!(meter.levels().integrated, None);
+        assert_eq!(meter.levels().true_peak, MIN_DB);
+    }
+}
@@ -0,0 +1,137 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(target_os = "linux")]
+use cpal::traits::{DeviceTrait, HostTrait};
+#[cfg(target_os = "linux")]
+use gag::Gag;
+use crate::audio::AudioError;
+
+// environment variable of the alsa plugin of PulseAudio that selects the recorded source
+const PULSE_SOURCE: &str = "PULSE_SOURCE";
+// true once `select_source` set PULSE_SOURCE to the monitor
+static SELECTED: AtomicBool = AtomicBool::new(false);
+
+/// prepares capturing what the default sink plays, "what you hear", for `find_device`
+///
+/// uses a monitor device listed by alsa if there is one, otherwise selects the monitor source
+/// of the default sink of PulseAudio or PipeWire for their alsa plugin via PULSE_SOURCE.
+/// changing the environment races with every other thread, so this has to be called
+/// before any threads get spawned, the pulse and default devices record the monitor from then on
+#[cfg(target_os = "linux")]
+pub fn select_source() -> Result<(), AudioError> {
+    let devices = input_devices()?;
+    if alsa_monitor(&devices).is_some() {
+        return Ok(());
+    }
+
+    let sink = default_sink().ok_or_else(|| {
+        AudioError::NoMonitor(String::from(
+            "could not get the default sink from pactl, is PulseAudio or PipeWire running?",
+        ))
+    })?;
+    let source = format!("{}.monitor", sink);
+    if !sources().contains(&source) {
+        return Err(AudioError::NoMonitor(format!("the default sink {} has no monitor source", sink)));
+    }
+
+    pulse_device(&devices)?;
+
+    std::env::set_var(PULSE_SOURCE, &source);
+    SELECTED.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// finds the input device that captures what the default sink plays, after `select_source`
+#[cfg(target_os = "linux")]
+pub fn find_device() -> Result<cpal::Device, AudioError> {
+    let mut devices = input_devices()?;
+    if let Some(i) = alsa_monitor(&devices) {
+        return Ok(devices.swap_remove(i));
+    }
+    if !SELECTED.load(Ordering::Relaxed) {
+        return Err(AudioError::NoMonitor(String::from(
+            "the monitor source has to be selected with monitor::select_source before any threads get spawned",
+        )));
+    }
+    let i = pulse_device(&devices)?;
+    Ok(devices.swap_remove(i))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn select_source() -> Result<(), AudioError> {
+    Err(not_linux())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn find_device() -> Result<cpal::Device, AudioError> {
+    Err(not_linux())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn not_linux() -> AudioError {
+    AudioError::NoMonitor(String::from(
+        "monitor sources only exist on linux, on windows --output-device captures the output",
+    ))
+}
+
+#[cfg(target_os = "linux")]
+fn input_devices() -> Result<Vec<cpal::Device>, AudioError> {
+    let _print_gag = Gag::stderr().ok();
+    Ok(cpal::default_host().input_devices().map_err(AudioError::Devices)?.collect())
+}
+
+#[cfg(target_os = "linux")]
+fn device_name(device: &cpal::Device) -> String {
+    device.name().unwrap_or_default()
+}
+
+// some alsa configurations list the monitors as devices of their own
+#[cfg(target_os = "linux")]
+fn alsa_monitor(devices: &[cpal::Device]) -> Option<usize> {
+    devices.iter().position(|device| device_name(device).to_lowercase().contains("monitor"))
+}
+
+// "default" is routed through PulseAudio on most systems that have no "pulse" device
+#[cfg(target_os = "linux")]
+fn pulse_device(devices: &[cpal::Device]) -> Result<usize, AudioError> {
+    ["pulse", "default"]
+        .iter()
+        .find_map(|plugin| devices.iter().position(|device| device_name(device) == *plugin))
+        .ok_or_else(|| {
+            AudioError::NoMonitor(String::from("alsa has no pulse device, is the alsa plugin of PulseAudio installed?"))
+        })
+}
+
+// name of the default sink, `get-default-sink` only exists since PulseAudio 15
+#[cfg(target_os = "linux")]
+fn default_sink() -> Option<String> {
+    if let Some(sink) = pactl(&["get-default-sink"]) {
+        let sink = sink.trim();
+        if !sink.is_empty() {
+            return Some(sink.to_string());
+        }
+    }
+    pactl(&["info"])?
+        .lines()
+        .find_map(|line| line.strip_prefix("Default Sink:"))
+        .map(|sink| sink.trim().to_string())
+}
+
+// names of all sources
+#[cfg(target_os = "linux")]
+fn sources() -> Vec<String> {
+    pactl(&["list", "short", "sources"])
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.split('\t').nth(1))
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+fn pactl(args: &[&str]) -> Option<String> {
+    let output = std::process::Command::new("pactl").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
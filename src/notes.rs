@@ -0,0 +1,57 @@
+//! notes of the piano in midi numbers, A0 is 21 and C8 is 108
+
+/// lowest and highest note of the piano, A0 and C8
+pub const LOWEST: u8 = 21;
+pub const HIGHEST: u8 = 108;
+
+const NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+/// frequency in hz of a note, fractional notes lie between two semitones
+pub fn frequency(note: f32) -> f32 {
+    440.0 * 2.0_f32.powf((note - 69.0) / 12.0)
+}
+
+/// note of a frequency in hz, the inverse of `frequency`
+pub fn from_frequency(frequency: f32) -> f32 {
+    69.0 + 12.0 * (frequency / 440.0).log2()
+}
+
+/// name with octave, e.g. "C#4"
+pub fn name(note: u8) -> String {
+    format!("{}{}", NAMES[note as usize % 12], note as i32 / 12 - 1)
+}
+
+/// true for the black keys of the piano
+pub fn is_black(note: u8) -> bool {
+    NAMES[note as usize % 12].ends_with('#')
+}
+
+/// level of every key of the piano from a spectrum, `frequency` is the one of every bin,
+/// the peak of the bins within a semitone or the nearest bin if there is none
+pub fn from_spectrum(buffer: &[f32], frequency: impl Fn(usize) -> f32) -> Vec<f32> {
+    if buffer.is_empty() {
+        return Vec::new();
+    }
+    let bins: Vec<f32> = (0..buffer.len()).map(|i| from_frequency(frequency(i).max(1.0))).collect();
+
+    (LOWEST..=HIGHEST)
+        .map(|note| {
+            let note = note as f32;
+            let within = bins
+                .iter()
+                .zip(buffer.iter())
+                .filter(|(bin, _)| (**bin - note).abs() <= 0.5)
+                .fold(None, |peak: Option<f32>, (_, value)| Some(peak.map_or(*value, |peak| peak.max(*value))));
+            within.unwrap_or_else(|| {
+                let nearest = bins
+                    .iter()
+                    .enumerate()
+                    .min_by(|(_, a), (_, b)| {
+                        (**a - note).abs().partial_cmp(&(**b - note).abs()).unwrap_or(std::cmp::Ordering::Equal)
+                    })
+                    .map_or(0, |(i, _)| i);
+                buffer[nearest]
+            })
+        })
+        .collect()
+}
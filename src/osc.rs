@@ -0,0 +1,177 @@
+use std::io;
+use std::net::UdpSocket;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+use crate::beat::Beat;
+use crate::config::Osc;
+use crate::idle::Activity;
+
+/// sends levels and beats as Open Sound Control bundles over udp,
+/// runs on its own thread independently of the rendering
+///
+/// every bundle contains `<prefix>/bands` with all band levels, `<prefix>/band/<i>` for every band,
+/// `<prefix>/rms` and `<prefix>/peak`, `<prefix>/bpm` once known and `<prefix>/beat` with its strength on beats
+pub fn init_osc_sender(
+    config: Osc,
+    event_sender: mpsc::Sender<audioviz::Event>,
+    activity: Arc<Activity>,
+    beats: mpsc::Receiver<Beat>,
+) -> io::Result<()> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.connect(&config.target)?;
+    let interval = Duration::from_secs_f32(1.0 / config.rate.max(1) as f32);
+
+    thread::spawn(move || {
+        let mut bpm: Option<f32> = None;
+        loop {
+            thread::sleep(interval);
+
+            let mut beat: Option<f32> = None;
+            for b in beats.try_iter() {
+                beat = Some(beat.unwrap_or(0.0).max(b.strength));
+                bpm = b.bpm;
+            }
+            if activity.is_idle() {
+                continue;
+            }
+
+            let (tx, rx) = mpsc::channel();
+            if event_sender.send(audioviz::Event::RequestData(tx)).is_err() {
+                return;
+            }
+            let spectrum = match rx.recv() {
+                Ok(spectrum) => spectrum,
+                Err(_) => return,
+            };
+            let bands = bands(&spectrum, config.bands);
+
+            let mut messages: Vec<Vec<u8>> = Vec::new();
+            messages.push(message(&format!("{}/bands", config.prefix), &bands));
+            for (i, band) in bands.iter().enumerate() {
+                messages.push(message(&format!("{}/band/{}", config.prefix, i), &[*band]));
+            }
+            messages.push(message(&format!("{}/rms", config.prefix), &[activity.rms()]));
+            messages.push(message(&format!("{}/peak", config.prefix), &[activity.peak()]));
+            if let Some(bpm) = bpm {
+                messages.push(message(&format!("{}/bpm", config.prefix), &[bpm]));
+            }
+            if let Some(beat) = beat {
+                messages.push(message(&format!("{}/beat", config.prefix), &[beat]));
+            }
+
+            // the receiver not listening is no reason to stop
+            socket.send(&bundle(&messages)).ok();
+        }
+    });
+
+    Ok(())
+}
+
+// averages the spectrum into `count` bands of equal width
+fn bands(spectrum: &[f32], count: usize) -> Vec<f32> {
+    if spectrum.is_empty() || count == 0 {
+        return vec![0.0; count];
+    }
+    (0..count)
+        .map(|band| {
+            let start = band * spectrum.len() / count;
+            let end = ((band + 1) * spectrum.len() / count).max(start + 1).min(spectrum.len());
+            let values = &spectrum[start.min(end - 1)..end];
+            values.iter().sum::<f32>() / values.len() as f32
+        })
+        .collect()
+}
+
+// osc message with float arguments
+fn message(address: &str, arguments: &[f32]) -> Vec<u8> {
+    let mut message: Vec<u8> = Vec::new();
+    push_string(&mut message, address);
+
+    let type_tags: String = std::iter::once(',').chain(arguments.iter().map(|_| 'f')).collect();
+    push_string(&mut message, &type_tags);
+
+    for argument in arguments {
+        message.extend_from_slice(&argument.to_be_bytes());
+    }
+    message
+}
+
+// osc bundle that is executed immediately
+fn bundle(messages: &[Vec<u8>]) -> Vec<u8> {
+    let mut bundle: Vec<u8> = Vec::new();
+    push_string(&mut bundle, "#bundle");
+    // time tag 1 means immediately
+    bundle.extend_from_slice(&1_u64.to_be_bytes());
+    for message in messages {
+        bundle.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        bundle.extend_from_slice(message);
+    }
+    bundle
+}
+
+// osc strings are null terminated and padded to a multiple of 4 bytes
+fn push_string(buffer: &mut Vec<u8>, string: &str) {
+    buffer.extend_from_slice(string.as_bytes());
+    let padding = 4 - string.len() % 4;
+    buffer.extend(std::iter::repeat_n(0, padding));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strings_are_padded() {
+        // a length of a multiple of 4 still gets a full word of nulls as terminator
+        let mut buffer = Vec::new();
+        push_string(&mut buffer, "/rms");
+        assert_eq!(buffer, b"/rms\0\0\0\0");
+
+        let mut buffer = Vec::new();
+        push_string(&mut buffer, "/peak");
+        assert_eq!(buffer, b"/peak\0\0\0");
+
+        let mut buffer = Vec::new();
+        push_string(&mut buffer, ",ff");
+        assert_eq!(buffer, b",ff\0");
+    }
+
+    #[test]
+    fn float_arguments_are_big_endian() {
+        let mut expected = b"/rms\0\0\0\0,f\0\0".to_vec();
+        expected.extend_from_slice(&[0x3f, 0x40, 0x00, 0x00]);
+        assert_eq!(message("/rms", &[0.75]), expected);
+
+        let mut expected = b"/a/bands\0\0\0\0,ff\0".to_vec();
+        expected.extend_from_slice(&[0x3f, 0x80, 0x00, 0x00, 0xc0, 0x00, 0x00, 0x00]);
+        assert_eq!(message("/a/bands", &[1.0, -2.0]), expected);
+    }
+
+    #[test]
+    fn bundles_have_header_and_time_tag() {
+        let first = message("/peak", &[1.0]);
+        let second = message("/rms", &[0.5]);
+        let bundle = bundle(&[first.clone(), second.clone()]);
+
+        let mut expected = b"#bundle\0".to_vec();
+        expected.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+        expected.extend_from_slice(&[0, 0, 0, 16]);
+        expected.extend_from_slice(&first);
+        expected.extend_from_slice(&[0, 0, 0, 16]);
+        expected.extend_from_slice(&second);
+        assert_eq!(first.len(), 16);
+        assert_eq!(bundle, expected);
+        assert_eq!(bundle.len() % 4, 0);
+    }
+
+    #[test]
+    fn bands_average_the_spectrum() {
+        assert_eq!(bands(&[1.0, 3.0, 2.0, 4.0], 2), vec![2.0, 3.0]);
+        assert_eq!(bands(&[1.0, 2.0, 3.0], 1), vec![2.0]);
+        // more bands than bins repeat the bins
+        assert_eq!(bands(&[1.0, 2.0], 4), vec![1.0, 1.0, 2.0, 2.0]);
+        assert_eq!(bands(&[], 3), vec![0.0; 3]);
+        assert!(bands(&[1.0], 0).is_empty());
+    }
+}
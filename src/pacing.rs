@@ -0,0 +1,93 @@
+use std::time::{Duration, Instant};
+use winit::event_loop::ControlFlow;
+
+/// limits the amount of redraws per second
+pub struct FramePacer {
+    frame_time: Option<Duration>,
+    next_frame: Instant,
+}
+
+impl FramePacer {
+    /// `fps` of 0 disables the limit
+    pub fn new(fps: u32) -> Self {
+        Self {
+            frame_time: if fps > 0 {
+                Some(Duration::from_secs_f64(1.0 / fps as f64))
+            } else {
+                None
+            },
+            next_frame: Instant::now(),
+        }
+    }
+
+    /// returns whether a frame should be drawn now and how long the event loop should wait
+    pub fn poll(&mut self) -> (bool, ControlFlow) {
+        let frame_time = match self.frame_time {
+            Some(frame_time) => frame_time,
+            None => return (true, ControlFlow::Poll),
+        };
+
+        let now = Instant::now();
+        if now < self.next_frame {
+            return (false, ControlFlow::WaitUntil(self.next_frame));
+        }
+
+        // schedules relative to the last deadline so that the frame rate does not drift,
+        // but does not try to catch up after falling behind
+        self.next_frame += frame_time;
+        if self.next_frame <= now {
+            self.next_frame = now + frame_time;
+        }
+        (true, ControlFlow::WaitUntil(self.next_frame))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn unlimited_draws_every_time() {
+        let mut pacer = FramePacer::new(0);
+        for _ in 0..3 {
+            assert_eq!(pacer.poll(), (true, ControlFlow::Poll));
+        }
+    }
+
+    #[test]
+    fn limits_the_frame_rate() {
+        let mut pacer = FramePacer::new(20);
+        let start = Instant::now();
+        let (draw, control_flow) = pacer.poll();
+        assert!(draw);
+        let deadline = match control_flow {
+            ControlFlow::WaitUntil(deadline) => deadline,
+            other => panic!("{:?}", other),
+        };
+        assert!(deadline >= start + Duration::from_millis(49) && deadline <= Instant::now() + Duration::from_millis(50));
+
+        // too early, waits for the same deadline
+        assert_eq!(pacer.poll(), (false, ControlFlow::WaitUntil(deadline)));
+
+        // the next deadline follows the last one instead of the time of the poll
+        thread::sleep(deadline - Instant::now() + Duration::from_millis(10));
+        assert_eq!(pacer.poll(), (true, ControlFlow::WaitUntil(deadline + Duration::from_millis(50))));
+    }
+
+    #[test]
+    fn does_not_catch_up() {
+        let mut pacer = FramePacer::new(100);
+        pacer.poll();
+        // five frames late, only one frame gets drawn
+        thread::sleep(Duration::from_millis(50));
+        let (draw, control_flow) = pacer.poll();
+        let after = Instant::now();
+        assert!(draw);
+        match control_flow {
+            ControlFlow::WaitUntil(deadline) => assert!(deadline > after && deadline <= after + Duration::from_millis(10)),
+            other => panic!("{:?}", other),
+        }
+        assert!(!pacer.poll().0);
+    }
+}
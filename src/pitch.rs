@@ -0,0 +1,215 @@
+use std::collections::VecDeque;
+use crate::config;
+use crate::published::Published;
+
+// time between two analysed windows
+const HOP_DURATION: f32 = 0.05;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pitch {
+    // fundamental frequency in hz
+    pub frequency: f32,
+    // 1.0 minus the aperiodicity of the window, near 1.0 for clean tones
+    pub clarity: f32,
+}
+
+/// fundamental frequency detector for monophonic input, using the YIN algorithm
+/// of de Cheveigné and Kawahara, that can be fed with any stream of f32 samples
+pub struct PitchDetector {
+    sample_rate: f32,
+    channels: usize,
+    threshold: f32,
+    // shortest and longest period in samples
+    min_period: usize,
+    max_period: usize,
+    hop: usize,
+
+    // the newest mono samples, twice the longest period and the neighbours of its minimum
+    window: VecDeque<f32>,
+    // interleaved samples of an incomplete frame
+    frame: Vec<f32>,
+    // new samples since the last analysis
+    since_analysis: usize,
+    difference: Vec<f32>,
+
+    pitch: Option<Pitch>,
+}
+
+impl PitchDetector {
+    pub fn new(sample_rate: u32, channels: u16, config: config::Tuner) -> Self {
+        let sample_rate = sample_rate as f32;
+        let max_period = (sample_rate / config.min_frequency.max(1.0)).ceil() as usize;
+        let min_period = ((sample_rate / config.max_frequency.max(1.0)).floor() as usize).clamp(2, max_period);
+
+        Self {
+            sample_rate,
+            channels: channels.max(1) as usize,
+            threshold: config.threshold,
+            min_period,
+            max_period,
+            hop: ((sample_rate * HOP_DURATION) as usize).max(1),
+            window: VecDeque::with_capacity(max_period * 2 + 2),
+            frame: Vec::with_capacity(channels.max(1) as usize),
+            since_analysis: 0,
+            difference: vec![0.0; max_period + 2],
+            pitch: None,
+        }
+    }
+
+    /// processes interleaved samples, returns true if a new window got analysed
+    pub fn process(&mut self, samples: &[f32]) -> bool {
+        let capacity = self.max_period * 2 + 2;
+        for sample in samples {
+            self.frame.push(*sample);
+            if self.frame.len() < self.channels {
+                continue;
+            }
+            let mono = self.frame.iter().sum::<f32>() / self.channels as f32;
+            self.frame.clear();
+
+            if self.window.len() == capacity {
+                self.window.pop_front();
+            }
+            self.window.push_back(mono);
+            self.since_analysis += 1;
+        }
+
+        if self.window.len() < capacity || self.since_analysis < self.hop {
+            return false;
+        }
+        self.since_analysis = 0;
+        self.pitch = self.analyse();
+        true
+    }
+
+    /// pitch of the last analysed window, None if it had no clear pitch
+    pub fn pitch(&self) -> Option<Pitch> {
+        self.pitch
+    }
+
+    fn analyse(&mut self) -> Option<Pitch> {
+        let size = self.max_period;
+        let window = self.window.make_contiguous();
+
+        // difference of the window to itself shifted by every period
+        for period in 1..=self.max_period + 1 {
+            self.difference[period] = window[..size]
+                .iter()
+                .zip(window[period..period + size].iter())
+                .map(|(a, b)| (a - b) * (a - b))
+                .sum();
+        }
+
+        // cumulative mean normalized difference, so that short periods do not win by default
+        let mut sum = 0.0;
+        self.difference[0] = 1.0;
+        for period in 1..=self.max_period + 1 {
+            sum += self.difference[period];
+            self.difference[period] = if sum > 0.0 { self.difference[period] * period as f32 / sum } else { 1.0 };
+        }
+
+        // first dip below the threshold, followed down to its minimum
+        let mut period = (self.min_period..=self.max_period).find(|p| self.difference[*p] < self.threshold)?;
+        while period < self.max_period && self.difference[period + 1] < self.difference[period] {
+            period += 1;
+        }
+
+        // parabola through the minimum and its neighbours, for periods between two samples
+        let (a, b, c) = (self.difference[period - 1], self.difference[period], self.difference[period + 1]);
+        let curvature = a - 2.0 * b + c;
+        let offset = if curvature > 0.0 { ((a - c) / (2.0 * curvature)).clamp(-1.0, 1.0) } else { 0.0 };
+
+        Some(Pitch {
+            frequency: self.sample_rate / (period as f32 + offset),
+            clarity: (1.0 - b).clamp(0.0, 1.0),
+        })
+    }
+}
+
+/// latest pitch, shared between the analysis thread and the renderers
+///
+/// pitch detection is expensive, so it only runs while a renderer shows the tuner
+pub type PitchState = Published<Pitch>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::TAU;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    fn detector(channels: u16) -> PitchDetector {
+        PitchDetector::new(SAMPLE_RATE, channels, crate::Config::default().tuner)
+    }
+
+    // a second of interleaved frames, `frame` gets the time in seconds
+    fn signal(channels: usize, mut frame: impl FnMut(f32) -> Vec<f32>) -> Vec<f32> {
+        let samples: Vec<f32> = (0..SAMPLE_RATE).flat_map(|i| frame(i as f32 / SAMPLE_RATE as f32)).collect();
+        assert_eq!(samples.len(), SAMPLE_RATE as usize * channels);
+        samples
+    }
+
+    // a tone with two overtones like most instruments
+    fn tone(frequency: f32, time: f32) -> f32 {
+        let phase = TAU * frequency * time;
+        0.5 * phase.sin() + 0.2 * (2.0 * phase).sin() + 0.1 * (3.0 * phase).sin()
+    }
+
+    fn detect(detector: &mut PitchDetector, samples: &[f32]) -> Option<Pitch> {
+        // chunks like the audio callback
+        for chunk in samples.chunks(1024) {
+            detector.process(chunk);
+        }
+        detector.pitch()
+    }
+
+    fn cents(frequency: f32, expected: f32) -> f32 {
+        1200.0 * (frequency / expected).log2()
+    }
+
+    #[test]
+    fn tones_at_the_limits() {
+        let config = crate::Config::default().tuner;
+        for frequency in [config.min_frequency, 110.0, 440.0, config.max_frequency] {
+            let pitch = detect(&mut detector(1), &signal(1, |time| vec![tone(frequency, time)]))
+                .unwrap_or_else(|| panic!("no pitch at {} hz", frequency));
+            assert!(cents(pitch.frequency, frequency).abs() < 2.0, "{} hz detected as {:?}", frequency, pitch);
+            assert!(pitch.clarity > 0.9);
+        }
+    }
+
+    #[test]
+    fn noise_has_no_pitch() {
+        let mut state: u32 = 0x1234_5678;
+        let noise = signal(1, |_| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            vec![state as f32 / u32::MAX as f32 * 2.0 - 1.0]
+        });
+        assert_eq!(detect(&mut detector(1), &noise), None);
+    }
+
+    #[test]
+    fn silence_has_no_pitch() {
+        assert_eq!(detect(&mut detector(1), &signal(1, |_| vec![0.0])), None);
+    }
+
+    #[test]
+    fn stereo_is_downmixed() {
+        // a tone on one channel only
+        let left = signal(2, |time| vec![tone(440.0, time), 0.0]);
+        let pitch = detect(&mut detector(2), &left).unwrap();
+        assert!(cents(pitch.frequency, 440.0).abs() < 2.0, "{:?}", pitch);
+
+        // the same tone out of phase cancels
+        let cancelled = signal(2, |time| vec![tone(440.0, time), -tone(440.0, time)]);
+        assert_eq!(detect(&mut detector(2), &cancelled), None);
+
+        // frames split between two calls
+        let mut detector = detector(2);
+        for chunk in left.chunks(1023) {
+            detector.process(chunk);
+        }
+        let pitch = detector.pitch().unwrap();
+        assert!(cents(pitch.frequency, 440.0).abs() < 2.0, "{:?}", pitch);
+    }
+}
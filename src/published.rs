@@ -0,0 +1,67 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// latest result of an analysis, shared between the analysis thread and the renderers
+///
+/// analyses that are only shown on demand, like the pitch and the meters,
+/// only run while a renderer keeps them active
+#[derive(Debug)]
+pub struct Published<T> {
+    active: AtomicBool,
+    value: Mutex<Option<T>>,
+}
+
+impl<T> Default for Published<T> {
+    fn default() -> Self {
+        Self {
+            active: AtomicBool::new(false),
+            value: Mutex::new(None),
+        }
+    }
+}
+
+impl<T: Clone> Published<T> {
+    /// starts or stops the analysis, stopping forgets the last result
+    pub fn set_active(&self, active: bool) {
+        if !self.active.swap(active, Ordering::Relaxed) || active {
+            return;
+        }
+        self.publish(None);
+    }
+
+    pub fn active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    pub fn publish(&self, value: Option<T>) {
+        *self.value.lock().unwrap() = value;
+    }
+
+    pub fn get(&self) -> Option<T> {
+        self.value.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deactivating_forgets() {
+        let published = Published::default();
+        assert!(!published.active());
+        assert_eq!(published.get(), None);
+
+        published.set_active(true);
+        published.publish(Some(440.0));
+        assert_eq!(published.get(), Some(440.0));
+
+        // activating again keeps the value
+        published.set_active(true);
+        assert_eq!(published.get(), Some(440.0));
+
+        published.set_active(false);
+        assert!(!published.active());
+        assert_eq!(published.get(), None);
+    }
+}
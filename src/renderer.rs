@@ -0,0 +1,169 @@
+use std::fmt::Display;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::config::Action;
+use crate::idle::Activity;
+use crate::server::Server;
+use crate::triple;
+
+// frames per second of backends without own pacing, if the configuration does not limit them
+const DEFAULT_FPS: u32 = 60;
+// fetches per refresh of audioviz, so that a new spectrum is at most half a refresh old
+const FETCHES_PER_REFRESH: u32 = 2;
+
+/// backend that draws the visualisation, e.g. into a window or the terminal
+pub trait Renderer {
+    type Error: Display;
+
+    /// takes the raw spectrum of the current frame, its `age` tells whether it is stale
+    fn update(&mut self, spectrum: &Spectrum);
+
+    fn render(&mut self) -> Result<(), Self::Error>;
+
+    /// new size of the output in pixels, or characters for the terminal
+    fn resize(&mut self, width: u32, height: u32);
+
+    /// returns whether the action was handled
+    fn input(&mut self, _action: Action) -> bool {
+        false
+    }
+
+    /// returns true while something changes without new audio data
+    fn animating(&self) -> bool {
+        false
+    }
+}
+
+/// spectrum of audioviz with the time it was fetched at
+#[derive(Debug, Clone)]
+pub struct Spectrum {
+    pub bins: Vec<f32>,
+    // when audioviz delivered it
+    pub time: Instant,
+    // counts up with every fetch, the same number means the same spectrum
+    pub sequence: u64,
+}
+
+impl Spectrum {
+    /// time since audioviz delivered the spectrum, growing while audioviz is stuck
+    pub fn age(&self) -> Duration {
+        self.time.elapsed()
+    }
+}
+
+/// provides the latest spectrum of audioviz without blocking and publishes it to the tcp clients
+///
+/// a thread fetches the spectrum from audioviz and hands it over through a triple buffer,
+/// so the render loop never waits for audioviz, it pauses while `activity` is idle
+pub struct Source {
+    spectrum: triple::Output<Spectrum>,
+    server: Option<Server>,
+    // sequence of the last spectrum sent to the tcp clients
+    published: u64,
+}
+
+impl Source {
+    /// `refresh_rate` is the one of the audioviz configuration
+    pub fn new(
+        event_sender: mpsc::Sender<audioviz::Event>,
+        activity: Arc<Activity>,
+        refresh_rate: usize,
+        server: Option<Server>,
+    ) -> Self {
+        let (mut input, output) = triple::channel(Spectrum {
+            bins: Vec::new(),
+            time: Instant::now(),
+            sequence: 0,
+        });
+        let interval = Duration::from_secs_f32(1.0 / (refresh_rate.max(1) as u32 * FETCHES_PER_REFRESH) as f32);
+
+        thread::spawn(move || {
+            let mut sequence = 0;
+            while !input.abandoned() {
+                // nothing changes while idle, the render loop sleeps as well
+                if activity.is_idle() {
+                    thread::sleep(interval);
+                    continue;
+                }
+                let (tx, rx) = mpsc::channel();
+                event_sender.send(audioviz::Event::RequestData(tx)).ok();
+                let bins = rx.recv();
+                let stopped = bins.is_err();
+                sequence += 1;
+
+                let spectrum = input.buffer();
+                spectrum.bins = bins.unwrap_or_default();
+                spectrum.time = Instant::now();
+                spectrum.sequence = sequence;
+                input.publish();
+
+                // audioviz stopped, the empty spectrum stays
+                if stopped {
+                    return;
+                }
+                thread::sleep(interval);
+            }
+        });
+
+        Self {
+            spectrum: output,
+            server,
+            published: 0,
+        }
+    }
+
+    /// latest spectrum, never blocks, empty if audioviz stopped
+    pub fn fetch(&mut self) -> &Spectrum {
+        let spectrum = self.spectrum.read();
+        // every spectrum only once, even if it gets rendered more often
+        if spectrum.sequence != self.published {
+            if let Some(server) = self.server.as_mut() {
+                server.publish(&spectrum.bins);
+            }
+            self.published = spectrum.sequence;
+        }
+        spectrum
+    }
+}
+
+/// draws one frame with the latest spectrum, shared by the event loop of the window and `run`
+pub fn draw<R: Renderer>(renderer: &mut R, source: &mut Source) -> Result<(), R::Error> {
+    renderer.update(source.fetch());
+    renderer.render()
+}
+
+/// returns true if a main loop can stop drawing until sound gets captured again,
+/// which the waker of `activity` reports if one is set
+pub fn can_sleep<R: Renderer>(renderer: &R, activity: &Activity) -> bool {
+    !renderer.animating() && activity.try_sleep()
+}
+
+/// main loop of the backends without window, never returns
+pub fn run<R: Renderer>(mut renderer: R, mut source: Source, activity: &Activity, fps: u32) -> ! {
+    let interval = Duration::from_secs_f32(1.0 / if fps > 0 { fps } else { DEFAULT_FPS } as f32);
+    loop {
+        thread::sleep(interval);
+        if can_sleep(&renderer, activity) {
+            continue;
+        }
+        if let Err(e) = draw(&mut renderer, &mut source) {
+            eprintln!("{}", e);
+        }
+    }
+}
+
+/// renders nothing, used to only publish the spectrum via --serve
+pub struct Headless;
+
+impl Renderer for Headless {
+    type Error = std::convert::Infallible;
+
+    fn update(&mut self, _spectrum: &Spectrum) {}
+
+    fn render(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn resize(&mut self, _width: u32, _height: u32) {}
+}
@@ -0,0 +1,239 @@
+use std::cell::UnsafeCell;
+use std::ptr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// counters of a ring buffer, shared with the rest of the application for diagnostics
+#[derive(Debug, Default)]
+pub struct Stats {
+    samples: AtomicU64,
+    overruns: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl Stats {
+    /// samples that got written into the ring buffer
+    pub fn samples(&self) -> u64 {
+        self.samples.load(Ordering::Relaxed)
+    }
+
+    /// writes that did not fit completely, because the consumer fell behind
+    pub fn overruns(&self) -> u64 {
+        self.overruns.load(Ordering::Relaxed)
+    }
+
+    /// samples that got dropped by overruns
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+struct Shared {
+    buffer: Box<[UnsafeCell<f32>]>,
+    // capacity - 1, the capacity is a power of two
+    mask: usize,
+    // total amount of written and read samples, wrapping
+    written: AtomicUsize,
+    read: AtomicUsize,
+    stats: Arc<Stats>,
+}
+
+// the producer only writes the free part and the consumer only reads the filled part
+unsafe impl Sync for Shared {}
+
+impl Shared {
+    fn capacity(&self) -> usize {
+        self.mask + 1
+    }
+
+    fn ptr(&self) -> *mut f32 {
+        // UnsafeCell<f32> has the same layout as f32
+        self.buffer.as_ptr() as *mut f32
+    }
+}
+
+/// creates a lock-free single producer single consumer ring buffer of samples,
+/// the capacity gets rounded up to a power of two, nothing allocates after this
+pub fn channel(capacity: usize, stats: Arc<Stats>) -> (Producer, Consumer) {
+    let capacity = capacity.max(1).next_power_of_two();
+    let shared = Arc::new(Shared {
+        buffer: (0..capacity).map(|_| UnsafeCell::new(0.0)).collect(),
+        mask: capacity - 1,
+        written: AtomicUsize::new(0),
+        read: AtomicUsize::new(0),
+        stats,
+    });
+    (Producer { shared: shared.clone() }, Consumer { shared })
+}
+
+/// writing end, meant for the real-time audio callback
+pub struct Producer {
+    shared: Arc<Shared>,
+}
+
+impl Producer {
+    /// writes as many samples as fit, returns false if the rest had to be dropped
+    pub fn push(&mut self, data: &[f32]) -> bool {
+        let shared = &self.shared;
+        let written = shared.written.load(Ordering::Relaxed);
+        let read = shared.read.load(Ordering::Acquire);
+        let free = shared.capacity() - written.wrapping_sub(read);
+        let count = data.len().min(free);
+
+        // in up to two parts, the second one wraps around to the start
+        let start = written & shared.mask;
+        let first = count.min(shared.capacity() - start);
+        unsafe {
+            ptr::copy_nonoverlapping(data.as_ptr(), shared.ptr().add(start), first);
+            ptr::copy_nonoverlapping(data.as_ptr().add(first), shared.ptr(), count - first);
+        }
+        shared.written.store(written.wrapping_add(count), Ordering::Release);
+
+        shared.stats.samples.fetch_add(count as u64, Ordering::Relaxed);
+        if count < data.len() {
+            shared.stats.overruns.fetch_add(1, Ordering::Relaxed);
+            shared.stats.dropped.fetch_add((data.len() - count) as u64, Ordering::Relaxed);
+            return false;
+        }
+        true
+    }
+}
+
+/// reading end, meant for the analysis thread
+pub struct Consumer {
+    shared: Arc<Shared>,
+}
+
+impl Consumer {
+    /// appends all available samples to `out` and returns how many there were
+    pub fn pop_into(&mut self, out: &mut Vec<f32>) -> usize {
+        let shared = &self.shared;
+        let read = shared.read.load(Ordering::Relaxed);
+        let written = shared.written.load(Ordering::Acquire);
+        let count = written.wrapping_sub(read);
+
+        let start = read & shared.mask;
+        let first = count.min(shared.capacity() - start);
+        unsafe {
+            out.extend_from_slice(std::slice::from_raw_parts(shared.ptr().add(start), first));
+            out.extend_from_slice(std::slice::from_raw_parts(shared.ptr(), count - first));
+        }
+        shared.read.store(read.wrapping_add(count), Ordering::Release);
+        count
+    }
+
+    /// true once the producer got dropped, samples may still be left to read
+    pub fn abandoned(&self) -> bool {
+        Arc::strong_count(&self.shared) == 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn ring(capacity: usize) -> (Producer, Consumer, Arc<Stats>) {
+        let stats = Arc::new(Stats::default());
+        let (producer, consumer) = channel(capacity, stats.clone());
+        (producer, consumer, stats)
+    }
+
+    #[test]
+    fn capacity_is_a_power_of_two() {
+        let (mut producer, mut consumer, _) = ring(5);
+        assert!(!producer.push(&[0.0; 9]));
+        let mut out = Vec::new();
+        assert_eq!(consumer.pop_into(&mut out), 8);
+    }
+
+    #[test]
+    fn wraps_around() {
+        let (mut producer, mut consumer, stats) = ring(8);
+        let mut out = Vec::new();
+        assert!(producer.push(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]));
+        assert_eq!(consumer.pop_into(&mut out), 6);
+
+        // starts at index 6 and continues at the start of the buffer
+        assert!(producer.push(&[7.0, 8.0, 9.0, 10.0, 11.0]));
+        assert_eq!(consumer.pop_into(&mut out), 5);
+        assert_eq!(out, [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 11.0]);
+
+        assert_eq!(consumer.pop_into(&mut out), 0);
+        assert_eq!(stats.samples(), 11);
+        assert_eq!(stats.overruns(), 0);
+    }
+
+    #[test]
+    fn overruns_drop_the_rest() {
+        let (mut producer, mut consumer, stats) = ring(4);
+        assert!(producer.push(&[1.0, 2.0, 3.0]));
+        // only one sample is free
+        assert!(!producer.push(&[4.0, 5.0, 6.0]));
+        assert!(!producer.push(&[7.0]));
+        assert_eq!(stats.samples(), 4);
+        assert_eq!(stats.overruns(), 2);
+        assert_eq!(stats.dropped(), 3);
+
+        // the oldest samples are kept
+        let mut out = Vec::new();
+        consumer.pop_into(&mut out);
+        assert_eq!(out, [1.0, 2.0, 3.0, 4.0]);
+        assert!(producer.push(&[8.0; 4]));
+        assert_eq!(stats.samples(), 8);
+        assert_eq!(stats.overruns(), 2);
+    }
+
+    #[test]
+    fn abandoned_after_the_producer_is_dropped() {
+        let (mut producer, mut consumer, _) = ring(4);
+        assert!(!consumer.abandoned());
+        producer.push(&[1.0, 2.0]);
+        drop(producer);
+        assert!(consumer.abandoned());
+
+        // the last samples can still be read
+        let mut out = Vec::new();
+        assert_eq!(consumer.pop_into(&mut out), 2);
+    }
+
+    #[test]
+    fn keeps_the_order_across_threads() {
+        const SAMPLES: usize = 100_000;
+        let (mut producer, mut consumer, stats) = ring(256);
+
+        let written = stats.clone();
+        let writer = thread::spawn(move || {
+            let mut next = 0;
+            while next < SAMPLES {
+                // continues after the part that fit, so that nothing gets lost
+                let block: Vec<f32> = (next..(next + 37).min(SAMPLES)).map(|i| i as f32).collect();
+                let before = written.samples();
+                if !producer.push(&block) {
+                    thread::yield_now();
+                }
+                next += (written.samples() - before) as usize;
+            }
+        });
+
+        let mut out = Vec::new();
+        let mut expected = 0;
+        while expected < SAMPLES {
+            out.clear();
+            let abandoned = consumer.abandoned();
+            if consumer.pop_into(&mut out) == 0 {
+                thread::yield_now();
+            }
+            for sample in out.iter() {
+                assert_eq!(*sample, expected as f32);
+                expected += 1;
+            }
+            if abandoned {
+                break;
+            }
+        }
+        writer.join().unwrap();
+        assert_eq!(expected, SAMPLES);
+        assert_eq!(stats.samples(), SAMPLES as u64);
+    }
+}
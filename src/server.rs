@@ -0,0 +1,160 @@
+use std::io::{self, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+use serde_derive::Serialize;
+use crate::beat::Beat;
+use crate::idle::Activity;
+
+// frames that can be queued per client before frames get dropped for it
+const CLIENT_QUEUE: usize = 8;
+
+// queues of the threads that write the encoded frames to each client
+type Clients = Arc<Mutex<Vec<mpsc::SyncSender<Arc<Vec<u8>>>>>>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    // one json object per line
+    Json,
+    // little endian, see `encode_binary`
+    Binary,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Format::Json),
+            "binary" => Ok(Format::Binary),
+            other => Err(format!("unknown format: {}, expected json or binary", other)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Frame<'a> {
+    // seconds since the server was started
+    time: f64,
+    peak: f32,
+    bpm: Option<f32>,
+    // strength of the beats since the last frame
+    beat: Option<f32>,
+    spectrum: &'a [f32],
+}
+
+/// publishes every frame of the spectrum to all connected tcp clients
+pub struct Server {
+    format: Format,
+    clients: Clients,
+    beats: mpsc::Receiver<Beat>,
+    activity: Arc<Activity>,
+    bpm: Option<f32>,
+    start: Instant,
+    address: SocketAddr,
+}
+
+impl Server {
+    pub fn bind<A: ToSocketAddrs>(
+        address: A,
+        format: Format,
+        beats: mpsc::Receiver<Beat>,
+        activity: Arc<Activity>,
+    ) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let address = listener.local_addr()?;
+        let clients: Clients = Arc::new(Mutex::new(Vec::new()));
+
+        let accepted_clients = clients.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let (tx, rx) = mpsc::sync_channel(CLIENT_QUEUE);
+                accepted_clients.lock().unwrap().push(tx);
+                thread::spawn(move || write_frames(stream, rx));
+            }
+        });
+
+        Ok(Self {
+            format,
+            clients,
+            beats,
+            activity,
+            bpm: None,
+            start: Instant::now(),
+            address,
+        })
+    }
+
+    /// address the server listens on, with the actual port if it was bound to port 0
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// sends the spectrum of the current frame together with the peak level of the last captured samples
+    /// and the beats since the last frame, never blocks, slow clients miss frames instead
+    pub fn publish(&mut self, spectrum: &[f32]) {
+        let peak = self.activity.peak();
+        let mut beat: Option<f32> = None;
+        for b in self.beats.try_iter() {
+            beat = Some(beat.unwrap_or(0.0).max(b.strength));
+            self.bpm = b.bpm;
+        }
+
+        let mut clients = self.clients.lock().unwrap();
+        if clients.is_empty() {
+            return;
+        }
+
+        let frame = Frame {
+            time: self.start.elapsed().as_secs_f64(),
+            peak,
+            bpm: self.bpm,
+            beat,
+            spectrum,
+        };
+        let message = Arc::new(match self.format {
+            Format::Json => encode_json(&frame),
+            Format::Binary => encode_binary(&frame),
+        });
+
+        clients.retain(|client| match client.try_send(message.clone()) {
+            Ok(_) | Err(mpsc::TrySendError::Full(_)) => true,
+            Err(mpsc::TrySendError::Disconnected(_)) => false,
+        });
+    }
+}
+
+fn write_frames(mut stream: TcpStream, frames: mpsc::Receiver<Arc<Vec<u8>>>) {
+    stream.set_nodelay(true).ok();
+    for frame in frames.iter() {
+        if stream.write_all(&frame).is_err() {
+            // dropping the receiver removes the client on the next frame
+            return;
+        }
+    }
+}
+
+fn encode_json(frame: &Frame) -> Vec<u8> {
+    let mut message = serde_json::to_vec(frame).expect("failed to serialize frame");
+    message.push(b'\n');
+    message
+}
+
+// u32 length of the rest of the message, f64 time, f32 peak, f32 bpm (NaN if unknown),
+// f32 beat strength (0.0 if no beat), u32 amount of bins, f32 for every bin
+fn encode_binary(frame: &Frame) -> Vec<u8> {
+    let length = 8 + 4 + 4 + 4 + 4 + frame.spectrum.len() * 4;
+    let mut message: Vec<u8> = Vec::with_capacity(4 + length);
+
+    message.extend_from_slice(&(length as u32).to_le_bytes());
+    message.extend_from_slice(&frame.time.to_le_bytes());
+    message.extend_from_slice(&frame.peak.to_le_bytes());
+    message.extend_from_slice(&frame.bpm.unwrap_or(f32::NAN).to_le_bytes());
+    message.extend_from_slice(&frame.beat.unwrap_or(0.0).to_le_bytes());
+    message.extend_from_slice(&(frame.spectrum.len() as u32).to_le_bytes());
+    for value in frame.spectrum {
+        message.extend_from_slice(&value.to_le_bytes());
+    }
+    message
+}
@@ -0,0 +1,416 @@
+use std::io::{self, Write};
+use colored::*;
+use crate::audio::AudioSource;
+use crate::config::{Config, Visualisation};
+use crate::graphics::mesh::{self, Vertex};
+use crate::graphics::scene::{Inputs, Scene};
+use crate::renderer::{self, Renderer, Source, Spectrum};
+
+// used if the size of the terminal can not be determined
+const DEFAULT_SIZE: (u32, u32) = (80, 24);
+// resets the colors, clears the screen and shows the cursor again
+const RESTORE: &str = "\x1b[0m\x1b[2J\x1b[H\x1b[?25h";
+
+/// renders the visualisation of `audio` into the terminal until the process gets interrupted
+pub fn run(audio: &AudioSource, source: Source, config: Config) -> ! {
+    let fps = config.fps;
+    renderer::run(Terminal::new(audio.inputs(), config), source, &audio.activity, fps)
+}
+
+/// renders the visualisation to the terminal instead of a window
+///
+/// the mesh of the visualisation gets rasterized into half blocks for Bars
+/// and into braille characters for Strings and Circle
+pub struct Terminal {
+    scene: Scene,
+    columns: u32,
+    rows: u32,
+    // last rendered frame
+    frame: String,
+}
+
+impl Terminal {
+    /// the terminal gets restored when it is dropped or the process gets interrupted
+    pub fn new(inputs: Inputs, config: Config) -> Self {
+        restore_on_signals();
+        // clears the screen and hides the cursor
+        print!("\x1b[2J\x1b[?25l");
+
+        Self {
+            scene: Scene::new(inputs, config),
+            columns: DEFAULT_SIZE.0,
+            rows: DEFAULT_SIZE.1,
+            frame: String::new(),
+        }
+    }
+}
+
+impl Renderer for Terminal {
+    type Error = io::Error;
+
+    fn update(&mut self, spectrum: &Spectrum) {
+        let (columns, rows) = match terminal_size::terminal_size() {
+            Some((terminal_size::Width(w), terminal_size::Height(h))) => (w as u32, h as u32),
+            None => DEFAULT_SIZE,
+        };
+        if (columns, rows) != (self.columns, self.rows) {
+            self.resize(columns, rows);
+        }
+
+        self.scene.update(spectrum);
+        self.frame = draw(&self.scene, self.columns as usize, self.rows as usize);
+    }
+
+    fn render(&mut self) -> io::Result<()> {
+        let mut stdout = io::stdout();
+        // moves the cursor to the top left instead of clearing, which would flicker
+        write!(stdout, "\x1b[H{}", self.frame)?;
+        stdout.flush()
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        self.columns = width.max(1);
+        self.rows = height.max(1);
+        print!("\x1b[2J");
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        restore();
+    }
+}
+
+fn restore() {
+    // the lock waits for a frame that is being written
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(RESTORE.as_bytes()).ok();
+    stdout.flush().ok();
+}
+
+// renderer::run never returns, so Ctrl-C would leave the colors of the last frame and no cursor
+#[cfg(unix)]
+fn restore_on_signals() {
+    use std::sync::Once;
+    use std::thread;
+    use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| {
+        let mut signals = match signal_hook::iterator::Signals::new([SIGINT, SIGTERM, SIGHUP]) {
+            Ok(signals) => signals,
+            Err(e) => {
+                eprintln!("{} {}", "failed to handle signals:".yellow().bold(), e);
+                return;
+            }
+        };
+        thread::spawn(move || {
+            if let Some(signal) = signals.forever().next() {
+                restore();
+                std::process::exit(128 + signal);
+            }
+        });
+    });
+}
+
+#[cfg(not(unix))]
+fn restore_on_signals() {}
+
+fn draw(scene: &Scene, columns: usize, rows: usize) -> String {
+    let braille = scene.config.visualisation != Visualisation::Bars;
+    let (width, height) = if braille { (columns * 2, rows * 4) } else { (columns, rows * 2) };
+
+    // pixels of both modes are roughly square
+    let min = width.min(height).max(1) as f32;
+    let size = [width as f32 / min, height as f32 / min];
+
+    let mut config = scene.config.clone();
+    // lines have to be at least two pixels wide to not fall between the pixels
+    config.width = config.width.max(800.0 / min);
+
+    // one bin per column, more would fall between the pixels
+    let mirrored = match config.visualisation {
+        Visualisation::Bars => config.bars.mirror,
+        Visualisation::Strings => config.strings.mirror,
+        Visualisation::Circle => config.circle.mirror,
+        Visualisation::PianoRoll | Visualisation::Tuner => false,
+    };
+    let bins = if mirrored { width / 2 } else { width };
+    let buffer = match config.visualisation {
+        Visualisation::Circle | Visualisation::PianoRoll => scene.buffer().to_vec(),
+        // drawn from the pitch instead
+        Visualisation::Tuner => Vec::new(),
+        Visualisation::Bars | Visualisation::Strings => resample(scene.buffer(), bins),
+    };
+
+    let (mut vertices, mut indices) = match config.visualisation {
+        Visualisation::Tuner => mesh::tuner(scene.cents(), &config, scene.theme(), scene.time(), size),
+        _ => mesh::from_buffer(&buffer, &config, scene.theme(), scene.time(), scene.pulse(), size),
+    };
+    // one pixel of the text per pixel of the terminal
+    scene.overlay([2.0 / width as f32, 2.0 / height as f32], &mut vertices, &mut indices);
+
+    let mut canvas = Canvas::new(width, height);
+    for triangle in indices.chunks_exact(3) {
+        canvas.fill_triangle(
+            vertices[triangle[0] as usize],
+            vertices[triangle[1] as usize],
+            vertices[triangle[2] as usize],
+        );
+    }
+
+    if braille { canvas.to_braille() } else { canvas.to_blocks() }
+}
+
+// combines or stretches the buffer to `len` bins, keeping the peaks
+fn resample(buffer: &[f32], len: usize) -> Vec<f32> {
+    if buffer.is_empty() || len == 0 {
+        return Vec::new();
+    }
+    (0..len)
+        .map(|i| {
+            let start = i * buffer.len() / len;
+            let end = ((i + 1) * buffer.len() / len).max(start + 1).min(buffer.len());
+            buffer[start.min(end - 1)..end].iter().fold(0.0_f32, |peak, value| peak.max(*value))
+        })
+        .collect()
+}
+
+struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<Option<[f32; 3]>>,
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![None; width * height],
+        }
+    }
+
+    // fills every pixel whose center lies inside the triangle, regardless of its winding
+    fn fill_triangle(&mut self, a: Vertex, b: Vertex, c: Vertex) {
+        let to_pixel = |v: &Vertex| -> [f32; 2] {
+            [
+                (v.position[0] + 1.0) / 2.0 * self.width as f32,
+                (1.0 - v.position[1]) / 2.0 * self.height as f32,
+            ]
+        };
+        let (pa, pb, pc) = (to_pixel(&a), to_pixel(&b), to_pixel(&c));
+
+        let edge = |p: [f32; 2], q: [f32; 2], r: [f32; 2]| -> f32 {
+            (q[0] - p[0]) * (r[1] - p[1]) - (q[1] - p[1]) * (r[0] - p[0])
+        };
+        let area = edge(pa, pb, pc);
+        if area == 0.0 || !area.is_finite() {
+            return;
+        }
+
+        let min_x = pa[0].min(pb[0]).min(pc[0]).floor().max(0.0) as usize;
+        let max_x = (pa[0].max(pb[0]).max(pc[0]).ceil().max(0.0) as usize).min(self.width);
+        let min_y = pa[1].min(pb[1]).min(pc[1]).floor().max(0.0) as usize;
+        let max_y = (pa[1].max(pb[1]).max(pc[1]).ceil().max(0.0) as usize).min(self.height);
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let p = [x as f32 + 0.5, y as f32 + 0.5];
+                let wa = edge(pb, pc, p) / area;
+                let wb = edge(pc, pa, p) / area;
+                let wc = edge(pa, pb, p) / area;
+                if wa < 0.0 || wb < 0.0 || wc < 0.0 {
+                    continue;
+                }
+                let mut color = [0.0; 3];
+                for (i, channel) in color.iter_mut().enumerate() {
+                    *channel = a.color[i] * wa + b.color[i] * wb + c.color[i] * wc;
+                }
+                self.pixels[y * self.width + x] = Some(color);
+            }
+        }
+    }
+
+    // two pixels per character, the upper one as foreground of ▀ and the lower one as background
+    fn to_blocks(&self) -> String {
+        let mut output = String::new();
+        for row in 0..self.height / 2 {
+            if row > 0 {
+                output.push('\n');
+            }
+            for column in 0..self.width {
+                let top = self.pixels[row * 2 * self.width + column];
+                let bottom = self.pixels[(row * 2 + 1) * self.width + column];
+                let cell = match (top, bottom) {
+                    (Some(top), Some(bottom)) => {
+                        let (r, g, b) = rgb(bottom);
+                        colorize("▀", top).on_truecolor(r, g, b).to_string()
+                    }
+                    (Some(top), None) => colorize("▀", top).to_string(),
+                    (None, Some(bottom)) => colorize("▄", bottom).to_string(),
+                    (None, None) => String::from(" "),
+                };
+                output.push_str(&cell);
+            }
+            output.push_str("\x1b[K");
+        }
+        output
+    }
+
+    // 2 x 4 pixels per braille character, colored with the average of its set pixels
+    fn to_braille(&self) -> String {
+        // bit of every dot of a braille character, indexed by [row][column]
+        const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+        let mut output = String::new();
+        for row in 0..self.height / 4 {
+            if row > 0 {
+                output.push('\n');
+            }
+            for column in 0..self.width / 2 {
+                let mut bits: u32 = 0;
+                let mut color = [0.0; 3];
+                let mut count: f32 = 0.0;
+
+                for (dy, dots) in DOTS.iter().enumerate() {
+                    for (dx, dot) in dots.iter().enumerate() {
+                        let pixel = self.pixels[(row * 4 + dy) * self.width + column * 2 + dx];
+                        if let Some(pixel) = pixel {
+                            bits |= dot;
+                            for i in 0..3 {
+                                color[i] += pixel[i];
+                            }
+                            count += 1.0;
+                        }
+                    }
+                }
+
+                if bits == 0 {
+                    output.push(' ');
+                } else {
+                    let character = std::char::from_u32(0x2800 + bits).unwrap_or(' ').to_string();
+                    output.push_str(&colorize(&character, color.map(|c| c / count)).to_string());
+                }
+            }
+            output.push_str("\x1b[K");
+        }
+        output
+    }
+}
+
+fn rgb(color: [f32; 3]) -> (u8, u8, u8) {
+    let channel = |c: f32| (c.clamp(0.0, 1.0) * 255.0) as u8;
+    (channel(color[0]), channel(color[1]), channel(color[2]))
+}
+
+fn colorize(string: &str, color: [f32; 3]) -> ColoredString {
+    let (r, g, b) = rgb(color);
+    string.truecolor(r, g, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // drops the escape sequences of colors and line endings
+    fn strip(string: &str) -> String {
+        let mut output = String::new();
+        let mut escape = false;
+        for character in string.chars() {
+            match character {
+                '\x1b' => escape = true,
+                'm' | 'K' if escape => escape = false,
+                _ if escape => {}
+                _ => output.push(character),
+            }
+        }
+        output
+    }
+
+    fn vertex(x: f32, y: f32) -> Vertex {
+        Vertex { position: [x, y, 0.0], color: [1.0, 0.5, 0.0] }
+    }
+
+    fn filled(canvas: &Canvas) -> Vec<String> {
+        canvas.pixels
+            .chunks_exact(canvas.width)
+            .map(|row| row.iter().map(|pixel| if pixel.is_some() { '#' } else { '.' }).collect())
+            .collect()
+    }
+
+    #[test]
+    fn braille_dots() {
+        // the dots of the left column are numbered downwards first, the bottom row comes last
+        let bits = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+        for (y, row) in bits.iter().enumerate() {
+            for (x, bit) in row.iter().enumerate() {
+                let mut canvas = Canvas::new(2, 4);
+                canvas.pixels[y * 2 + x] = Some([1.0; 3]);
+                let expected = std::char::from_u32(0x2800 + bit).unwrap();
+                assert_eq!(strip(&canvas.to_braille()), expected.to_string());
+            }
+        }
+
+        let mut canvas = Canvas::new(4, 8);
+        canvas.pixels = vec![Some([1.0; 3]); 32];
+        canvas.pixels[0] = None;
+        assert_eq!(strip(&canvas.to_braille()), "⣾⣿\n⣿⣿");
+        assert_eq!(strip(&Canvas::new(2, 4).to_braille()), " ");
+    }
+
+    #[test]
+    fn block_glyphs() {
+        let mut canvas = Canvas::new(4, 2);
+        canvas.pixels[0] = Some([1.0, 0.0, 0.0]);
+        canvas.pixels[2] = Some([1.0, 0.0, 0.0]);
+        canvas.pixels[5] = Some([0.0, 0.0, 1.0]);
+        canvas.pixels[6] = Some([0.0, 0.0, 1.0]);
+        // upper half, lower half, both and nothing
+        assert_eq!(strip(&canvas.to_blocks()), "▀▄▀ ");
+
+        // both halves need the lower pixel as background
+        colored::control::set_override(true);
+        let blocks = canvas.to_blocks();
+        let cells: Vec<&str> = blocks.split("\x1b[0m").collect();
+        // truecolor or the closest of the 16 colors, depending on COLORTERM
+        let parameters = |cell: &str| -> Vec<String> {
+            let start = cell.find("\x1b[").unwrap() + 2;
+            let end = start + cell[start..].find('m').unwrap();
+            cell[start..end].split(';').map(String::from).collect()
+        };
+        let background = |cell: &str| parameters(cell).iter().any(|p| p.starts_with('4') || p.starts_with("10"));
+        assert!(!background(cells[0]) && !background(cells[1]), "{:?}", blocks);
+        assert!(background(cells[2]), "{:?}", blocks);
+    }
+
+    #[test]
+    fn resampling_keeps_the_peaks() {
+        assert_eq!(resample(&[1.0, 3.0, 2.0, 0.0], 2), [3.0, 2.0]);
+        assert_eq!(resample(&[1.0, 3.0, 2.0], 1), [3.0]);
+        assert_eq!(resample(&[1.0, 2.0], 4), [1.0, 1.0, 2.0, 2.0]);
+        assert_eq!(resample(&[1.0, 2.0, 3.0], 3), [1.0, 2.0, 3.0]);
+        assert!(resample(&[], 4).is_empty());
+        assert!(resample(&[1.0], 0).is_empty());
+    }
+
+    #[test]
+    fn triangles_fill_the_pixel_centers() {
+        // the lower left half of the canvas, counter clockwise
+        let mut canvas = Canvas::new(4, 4);
+        canvas.fill_triangle(vertex(-1.0, -1.0), vertex(1.0, -1.0), vertex(-1.0, 1.0));
+        assert_eq!(filled(&canvas), ["#...", "##..", "###.", "####"]);
+        assert_eq!(canvas.pixels[15], Some([1.0, 0.5, 0.0]));
+
+        // clockwise and beyond the edges
+        let mut canvas = Canvas::new(4, 4);
+        canvas.fill_triangle(vertex(0.0, 0.0), vertex(0.0, 3.0), vertex(3.0, 0.0));
+        assert_eq!(filled(&canvas), ["..##", "..##", "....", "...."]);
+
+        // without area or with invalid positions nothing gets drawn
+        let mut canvas = Canvas::new(4, 4);
+        canvas.fill_triangle(vertex(-1.0, -1.0), vertex(0.0, 0.0), vertex(1.0, 1.0));
+        canvas.fill_triangle(vertex(-1.0, -1.0), vertex(f32::NAN, 0.0), vertex(1.0, 1.0));
+        assert!(canvas.pixels.iter().all(|pixel| pixel.is_none()));
+    }
+}
@@ -0,0 +1,156 @@
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+
+// bits of `back`: index of the buffer and whether it holds a value the output did not see yet
+const INDEX: u8 = 0b011;
+const FRESH: u8 = 0b100;
+
+struct Shared<T> {
+    buffers: [UnsafeCell<T>; 3],
+    // the buffer between input and output, swapped with theirs
+    back: AtomicU8,
+}
+
+// input and output only ever access the buffer they own
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+/// creates a lock-free triple buffer, the output always reads the latest published value
+/// without blocking and the input never waits for the output
+pub fn channel<T: Clone>(initial: T) -> (Input<T>, Output<T>) {
+    let shared = Arc::new(Shared {
+        buffers: [
+            UnsafeCell::new(initial.clone()),
+            UnsafeCell::new(initial.clone()),
+            UnsafeCell::new(initial),
+        ],
+        back: AtomicU8::new(1),
+    });
+    (
+        Input {
+            shared: shared.clone(),
+            index: 0,
+        },
+        Output { shared, index: 2 },
+    )
+}
+
+/// writing end of a triple buffer
+pub struct Input<T> {
+    shared: Arc<Shared<T>>,
+    index: u8,
+}
+
+impl<T> Input<T> {
+    /// buffer the next value gets written into, it still contains an older value
+    pub fn buffer(&mut self) -> &mut T {
+        unsafe { &mut *self.shared.buffers[self.index as usize].get() }
+    }
+
+    /// makes the written buffer the latest value
+    pub fn publish(&mut self) {
+        let back = self.shared.back.swap(self.index | FRESH, Ordering::AcqRel);
+        self.index = back & INDEX;
+    }
+
+    /// true once the output got dropped
+    pub fn abandoned(&self) -> bool {
+        Arc::strong_count(&self.shared) == 1
+    }
+}
+
+/// reading end of a triple buffer
+pub struct Output<T> {
+    shared: Arc<Shared<T>>,
+    index: u8,
+}
+
+impl<T> Output<T> {
+    /// latest published value, the same one again if nothing new was published
+    pub fn read(&mut self) -> &T {
+        if self.shared.back.load(Ordering::Relaxed) & FRESH != 0 {
+            let back = self.shared.back.swap(self.index, Ordering::AcqRel);
+            self.index = back & INDEX;
+        }
+        unsafe { &*self.shared.buffers[self.index as usize].get() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn reads_the_published_value() {
+        let (mut input, mut output) = channel(0);
+        assert_eq!(*output.read(), 0);
+        *input.buffer() = 1;
+        input.publish();
+        assert_eq!(*output.read(), 1);
+    }
+
+    #[test]
+    fn reads_the_same_value_without_publish() {
+        let (mut input, mut output) = channel(0);
+        *input.buffer() = 1;
+        input.publish();
+        assert_eq!(*output.read(), 1);
+
+        // writing without publishing is not visible, and the buffer of the output is not swapped again
+        *input.buffer() = 2;
+        assert_eq!(*output.read(), 1);
+        assert_eq!(*output.read(), 1);
+        input.publish();
+        assert_eq!(*output.read(), 2);
+    }
+
+    #[test]
+    fn only_the_newest_value_is_visible() {
+        let (mut input, mut output) = channel(0);
+        for value in 1..=5 {
+            *input.buffer() = value;
+            input.publish();
+        }
+        assert_eq!(*output.read(), 5);
+        assert_eq!(*output.read(), 5);
+
+        // the input keeps writing into buffers the output does not read
+        for value in 6..=8 {
+            *input.buffer() = value;
+            input.publish();
+            assert_eq!(*output.read(), value);
+        }
+    }
+
+    #[test]
+    fn abandoned_after_the_output_is_dropped() {
+        let (input, output) = channel(0);
+        assert!(!input.abandoned());
+        drop(output);
+        assert!(input.abandoned());
+    }
+
+    #[test]
+    fn values_are_never_torn() {
+        const VALUES: u64 = 100_000;
+        let (mut input, mut output) = channel((0u64, 0u64));
+
+        let writer = thread::spawn(move || {
+            for value in 1..=VALUES {
+                *input.buffer() = (value, value);
+                input.publish();
+            }
+        });
+
+        let mut last = 0;
+        while last < VALUES {
+            let (first, second) = *output.read();
+            assert_eq!(first, second);
+            // never goes back to an older value
+            assert!(first >= last);
+            last = first;
+        }
+        writer.join().unwrap();
+    }
+}
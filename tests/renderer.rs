@@ -0,0 +1,210 @@
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+use audiovis::config::Action;
+use audiovis::graphics::mesh::Vertex;
+use audiovis::graphics::offscreen::Offscreen;
+use audiovis::graphics::scene::Scene;
+use audiovis::idle::Activity;
+use audiovis::renderer::Spectrum;
+use audiovis::{mesh, renderer, Config, Inputs, Renderer, Source};
+
+const SPECTRUM: [f32; 4] = [0.0, 0.5, 1.0, 1.5];
+
+const CONFIG: &str = r#"
+visualisation = "Bars"
+theme = "flat"
+
+[[themes]]
+name = "flat"
+stops = [
+    { position = 0.0, color = [0.0, 0.0, 0.0] },
+    { position = 1.0, color = [1.0, 1.0, 1.0] },
+]
+background = [0.0, 0.0, 0.0]
+
+[bars]
+mirror = false
+"#;
+
+// keeps the mesh of the last frame instead of drawing it
+struct Mock {
+    scene: Scene,
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+    frames: usize,
+}
+
+impl Renderer for Mock {
+    type Error = std::convert::Infallible;
+
+    fn update(&mut self, spectrum: &Spectrum) {
+        self.scene.update(spectrum);
+    }
+
+    fn render(&mut self) -> Result<(), Self::Error> {
+        let (vertices, indices) = self.scene.mesh([1.0, 1.0]);
+        self.vertices = vertices;
+        self.indices = indices;
+        self.frames += 1;
+        Ok(())
+    }
+
+    fn resize(&mut self, _width: u32, _height: u32) {}
+
+    fn input(&mut self, action: Action) -> bool {
+        self.scene.handle_action(action);
+        true
+    }
+
+    fn animating(&self) -> bool {
+        self.scene.animating()
+    }
+}
+
+// answers the requests of the source with a fixed spectrum like audioviz
+fn analysis() -> mpsc::Sender<audioviz::Event> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for event in receiver.iter() {
+            if let audioviz::Event::RequestData(reply) = event {
+                reply.send(SPECTRUM.to_vec()).ok();
+            }
+        }
+    });
+    sender
+}
+
+fn setup() -> (Mock, Source, Arc<Activity>) {
+    let config = Config::parse(CONFIG).unwrap();
+    let sender = analysis();
+    let activity = Arc::new(Activity::new(config.idle));
+    let source = Source::new(sender.clone(), activity.clone(), config.audio.refresh_rate, None);
+    let mock = Mock {
+        scene: Scene::new(Inputs::new(sender), config),
+        vertices: Vec::new(),
+        indices: Vec::new(),
+        frames: 0,
+    };
+    (mock, source, activity)
+}
+
+// draws until the first spectrum arrived from the fetching thread
+fn draw_spectrum(mock: &mut Mock, source: &mut Source) {
+    for _ in 0..200 {
+        renderer::draw(mock, source).unwrap();
+        if !mock.scene.buffer().is_empty() {
+            return;
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+    panic!("no spectrum received");
+}
+
+#[test]
+fn draws_bars_of_the_spectrum() {
+    let (mut mock, mut source, _activity) = setup();
+    draw_spectrum(&mut mock, &mut source);
+    assert_eq!(mock.scene.buffer(), SPECTRUM);
+
+    // the scene draws what mesh::from_buffer generates from its buffer
+    let scene = &mock.scene;
+    let (vertices, indices) = mesh::from_buffer(scene.buffer(), &scene.config, scene.theme(), scene.time(), 0.0, [1.0, 1.0]);
+    assert_eq!(indices, mock.indices);
+    let positions = |vertices: &[Vertex]| vertices.iter().map(|vertex| vertex.position).collect::<Vec<_>>();
+    assert_eq!(positions(&vertices), positions(&mock.vertices));
+
+    // one quad of two counter clockwise triangles per bar, from the bottom to half the value
+    assert_eq!(mock.vertices.len(), SPECTRUM.len() * 4);
+    assert_eq!(mock.indices.len(), SPECTRUM.len() * 6);
+    for (i, (quad, value)) in mock.vertices.chunks_exact(4).zip(SPECTRUM.iter()).enumerate() {
+        let left = -1.0 + 0.5 * i as f32;
+        let top = -1.0 + value;
+        let corners: Vec<[f32; 2]> = quad.iter().map(|vertex| [vertex.position[0], vertex.position[1]]).collect();
+        assert_eq!(corners, vec![[left, -1.0], [left + 0.5, -1.0], [left + 0.5, top], [left, top]]);
+        // black at the bottom, the gradient at the height at the top
+        assert_eq!(quad[0].color, [0.0, 0.0, 0.0]);
+        assert_eq!(quad[3].color, [value / 2.0; 3]);
+    }
+    for triangle in mock.indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| mock.vertices[triangle[i] as usize].position);
+        let area = (b[0] - a[0]) * (c[1] - a[1]) - (c[0] - a[0]) * (b[1] - a[1]);
+        // the first bar has no height
+        assert!(area >= 0.0);
+    }
+}
+
+#[test]
+fn actions_reach_the_scene() {
+    let (mut mock, mut source, activity) = setup();
+    draw_spectrum(&mut mock, &mut source);
+
+    assert!(mock.input(Action::NextVisualisation));
+    renderer::draw(&mut mock, &mut source).unwrap();
+    assert_eq!(mock.scene.config.visualisation, audiovis::Visualisation::Strings);
+    // the notification has to disappear, so the loop must not sleep
+    assert!(mock.animating());
+    assert!(!renderer::can_sleep(&mock, &activity));
+    assert!(mock.frames > 1);
+}
+
+#[test]
+fn stale_spectra_fall() {
+    let (mut mock, _source, _activity) = setup();
+    let mut spectrum = Spectrum { bins: SPECTRUM.to_vec(), time: Instant::now(), sequence: 1 };
+    mock.update(&spectrum);
+    assert_eq!(mock.scene.buffer(), SPECTRUM);
+
+    // audioviz got stuck, the same spectrum is not shown as if it was new
+    spectrum.time -= Duration::from_secs(1);
+    thread::sleep(Duration::from_millis(50));
+    mock.update(&spectrum);
+    let buffer = mock.scene.buffer().to_vec();
+    for (value, fresh) in buffer.iter().zip(SPECTRUM.iter()) {
+        assert!(*value < *fresh || *fresh == 0.0, "{:?}", buffer);
+    }
+    thread::sleep(Duration::from_millis(50));
+    mock.update(&spectrum);
+    assert!(mock.scene.buffer()[3] < buffer[3]);
+
+    // and recovers with the next fresh one
+    spectrum.time = Instant::now();
+    mock.update(&spectrum);
+    assert_eq!(mock.scene.buffer(), SPECTRUM);
+}
+
+#[test]
+fn offscreen_renders_the_bars() {
+    let config = Config::parse(CONFIG).unwrap();
+    let sender = analysis();
+    let activity = Arc::new(Activity::new(config.idle));
+    let mut source = Source::new(sender.clone(), activity, config.audio.refresh_rate, None);
+    let mut offscreen = match pollster::block_on(Offscreen::new(Inputs::new(sender), config, [16, 16])) {
+        Ok(offscreen) => offscreen,
+        Err(e) => {
+            // machines without any graphics adapter, even a software one, can not run it
+            eprintln!("skipped: {}", e);
+            return;
+        }
+    };
+    for _ in 0..200 {
+        renderer::draw(&mut offscreen, &mut source).unwrap();
+        if !offscreen.visualiser().scene().buffer().is_empty() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+    renderer::draw(&mut offscreen, &mut source).unwrap();
+
+    let frame = offscreen.frame().unwrap();
+    assert_eq!([frame.width, frame.height], [16, 16]);
+    assert_eq!(frame.pixels.len(), 16 * 16 * 4);
+    let pixel = |x: usize, y: usize| &frame.pixels[(y * 16 + x) * 4..(y * 16 + x) * 4 + 4];
+    // the first bar has no height, the last one reaches three quarters up and gets brighter to its top
+    for y in 0..16 {
+        assert_eq!(pixel(1, y), [0, 0, 0, 255]);
+    }
+    assert_eq!(pixel(13, 1), [0, 0, 0, 255]);
+    assert!(pixel(13, 5)[0] > pixel(13, 14)[0]);
+    assert!(pixel(13, 5)[0] > 100);
+}
@@ -0,0 +1,80 @@
+use std::convert::TryInto;
+use std::io::{BufRead, BufReader, Read};
+use std::net::TcpStream;
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+use audiovis::beat::Beat;
+use audiovis::idle::Activity;
+use audiovis::server::{Format, Server};
+use audiovis::Config;
+
+const SPECTRUM: [f32; 3] = [0.0, 0.5, 1.25];
+
+// server on a free port with a peak of 0.75 and a client connected to it
+fn connect(format: Format) -> (Server, mpsc::Sender<Beat>, TcpStream) {
+    let activity = Arc::new(Activity::new(Config::default().idle));
+    activity.process(&[0.5, -0.75]);
+    let (beats, receiver) = mpsc::channel();
+    let server = Server::bind("127.0.0.1:0", format, receiver, activity).unwrap();
+    let client = TcpStream::connect(server.local_addr()).unwrap();
+    client.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+    (server, beats, client)
+}
+
+// the client gets accepted in the background, so frames are published until the first one arrives
+fn publish_until_received(server: &mut Server, beats: &mpsc::Sender<Beat>, client: &TcpStream) {
+    let mut byte = [0];
+    for _ in 0..100 {
+        beats.send(Beat { strength: 2.0, bpm: Some(120.0) }).unwrap();
+        server.publish(&SPECTRUM);
+        if client.peek(&mut byte).is_ok() {
+            return;
+        }
+    }
+    panic!("no frame received");
+}
+
+#[test]
+fn json_lines() {
+    let (mut server, beats, client) = connect(Format::Json);
+    publish_until_received(&mut server, &beats, &client);
+
+    let mut line = String::new();
+    BufReader::new(client).read_line(&mut line).unwrap();
+    assert!(line.ends_with('\n'));
+    let frame: serde_json::Value = serde_json::from_str(&line).unwrap();
+    assert!(frame["time"].as_f64().unwrap() >= 0.0);
+    assert_eq!(frame["peak"], 0.75);
+    assert_eq!(frame["bpm"], 120.0);
+    assert_eq!(frame["beat"], 2.0);
+    assert_eq!(frame["spectrum"], serde_json::json!(SPECTRUM));
+}
+
+#[test]
+fn binary_frames() {
+    let (mut server, beats, mut client) = connect(Format::Binary);
+    publish_until_received(&mut server, &beats, &client);
+
+    let mut length = [0; 4];
+    client.read_exact(&mut length).unwrap();
+    let length = u32::from_le_bytes(length) as usize;
+    assert_eq!(length, 8 + 4 * 4 + SPECTRUM.len() * 4);
+
+    let mut message = vec![0; length];
+    client.read_exact(&mut message).unwrap();
+    let f32_at = |offset: usize| f32::from_le_bytes(message[offset..offset + 4].try_into().unwrap());
+    assert!(f64::from_le_bytes(message[0..8].try_into().unwrap()) >= 0.0);
+    assert_eq!(f32_at(8), 0.75);
+    assert_eq!(f32_at(12), 120.0);
+    assert_eq!(f32_at(16), 2.0);
+    assert_eq!(u32::from_le_bytes(message[20..24].try_into().unwrap()) as usize, SPECTRUM.len());
+    let spectrum: Vec<f32> = (0..SPECTRUM.len()).map(|i| f32_at(24 + i * 4)).collect();
+    assert_eq!(spectrum, SPECTRUM);
+}
+
+#[test]
+fn formats_parse() {
+    assert_eq!("json".parse(), Ok(Format::Json));
+    assert_eq!("binary".parse(), Ok(Format::Binary));
+    assert!("xml".parse::<Format>().is_err());
+}